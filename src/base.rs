@@ -1,17 +1,34 @@
 use bevy::app::{App, PluginGroupBuilder};
 use bevy::prelude::*;
 
+use self::actions::ActionPlugin;
 use self::camera::CameraControllerPlugin;
+use self::console::{ConsoleOverlayPlugin, ConsolePlugin};
+use self::hud::HudPlugin;
 use self::settings::*;
 use self::velocity::VelocityPlugin;
 
+pub mod actions;
+pub mod rng;
+pub mod scheduler;
 pub mod timer;
+pub mod units;
 pub mod velocity;
 pub mod camera;
+pub mod console;
+pub mod hud;
 pub mod settings;
 pub mod appstate;
 
 
+/// egui is also brought by the editor when the `editor` feature is on, it must only be added once
+pub(crate) fn ensure_egui(app: &mut App) {
+    if !app.world.contains_resource::<bevy_egui::EguiContext>() {
+        app.add_plugin(bevy_egui::EguiPlugin);
+    }
+}
+
+
 pub fn frame_update(time: Res<Time>) {
     info!(
         "time since last frame_update: {:?}",
@@ -26,8 +43,12 @@ impl PluginGroup for BaseLogicPlugins {
     fn build(self) -> PluginGroupBuilder {
         PluginGroupBuilder::start::<Self>()
             .add(GameSettingsPlugin)
+            .add(ActionPlugin)
             .add(CameraControllerPlugin)
             .add(VelocityPlugin)
+            .add(ConsolePlugin)
+            .add(ConsoleOverlayPlugin)
+            .add(HudPlugin)
     }
 }
 
@@ -0,0 +1,247 @@
+use std::collections::HashSet;
+
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::settings::InputSettings;
+
+/// Everything the player can do with an input, systems check those instead of raw keys
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    CameraUp,
+    CameraDown,
+    CameraLeft,
+    CameraRight,
+    CameraDrag,
+    CameraFollow,
+    CameraFocus,
+    ExitSystemView,
+    QuickSave,
+    ToggleConsole,
+    PauseSim,
+    SimFaster,
+    SimSlower,
+    ContextMenu,
+    /// Click or drag a box to select ships
+    Select,
+    /// Held while selecting to add to the selection instead of replacing it
+    AddToSelection,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Modifier {
+    Shift,
+    Control,
+    Alt,
+}
+
+impl Modifier {
+    fn keys(&self) -> [KeyCode; 2] {
+        match self {
+            Modifier::Shift => [KeyCode::LShift, KeyCode::RShift],
+            Modifier::Control => [KeyCode::LControl, KeyCode::RControl],
+            Modifier::Alt => [KeyCode::LAlt, KeyCode::RAlt],
+        }
+    }
+
+    fn is_modifier_key(key: KeyCode) -> bool {
+        [Modifier::Shift, Modifier::Control, Modifier::Alt].iter().any(|m| m.keys().contains(&key))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum InputKind {
+    Key(KeyCode),
+    /// Physical key, stays at the same place whatever the keyboard layout
+    Scan(u32),
+    Mouse(MouseButton),
+    /// Any connected gamepad
+    Gamepad(GamepadButtonType),
+    /// Either side of a modifier key, alone
+    Modifier(Modifier),
+}
+
+/// An input and the modifiers that have to be held with it
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Binding {
+    pub input: InputKind,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+}
+
+impl Binding {
+    pub fn key(key: KeyCode) -> Self {
+        Self { input: InputKind::Key(key), modifiers: Vec::new() }
+    }
+
+    pub fn scan(code: u32) -> Self {
+        Self { input: InputKind::Scan(code), modifiers: Vec::new() }
+    }
+
+    pub fn mouse(button: MouseButton) -> Self {
+        Self { input: InputKind::Mouse(button), modifiers: Vec::new() }
+    }
+
+    pub fn modifier(modifier: Modifier) -> Self {
+        Self { input: InputKind::Modifier(modifier), modifiers: Vec::new() }
+    }
+
+    pub fn is_keyboard(&self) -> bool {
+        matches!(self.input, InputKind::Key(_) | InputKind::Scan(_) | InputKind::Modifier(_))
+    }
+
+    pub fn with(mut self, modifier: Modifier) -> Self {
+        if !self.modifiers.contains(&modifier) {
+            self.modifiers.push(modifier);
+        }
+        self
+    }
+}
+
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(ActionState::default())
+            .insert_resource(PendingRebind::default())
+            .add_event::<BindingConflictEvent>()
+            .add_system_to_stage(CoreStage::PreUpdate, capture_rebind
+                .label(ActionSystem::Rebind)
+                .after(InputSystem))
+            .add_system_to_stage(CoreStage::PreUpdate, update_action_state
+                .label(ActionSystem::Update)
+                .after(ActionSystem::Rebind));
+    }
+}
+
+/// Anything reading [`ActionState`] outside of `CoreStage::Update` should run after `ActionSystem::Update`
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ActionSystem {
+    Rebind,
+    Update,
+}
+
+/// Actions held, pressed or released this frame
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+    /// A text field has the keyboard, only mouse and gamepad bindings count
+    pub keyboard_captured: bool,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+
+    /// Used by non-input sources, like the console or replays, to trigger an action
+    pub fn press(&mut self, action: Action) {
+        if self.pressed.insert(action) {
+            self.just_pressed.insert(action);
+        }
+    }
+}
+
+/// Raw input resources, all optional so headless runs work without them
+#[derive(bevy::ecs::system::SystemParam)]
+pub struct RawInputs<'w, 's> {
+    keys: Option<Res<'w, Input<KeyCode>>>,
+    scans: Option<Res<'w, Input<ScanCode>>>,
+    mouse: Option<Res<'w, Input<MouseButton>>>,
+    pads: Option<Res<'w, Input<GamepadButton>>>,
+    #[system_param(ignore)]
+    _marker: std::marker::PhantomData<&'s ()>,
+}
+
+impl<'w, 's> RawInputs<'w, 's> {
+    fn modifiers_held(&self, modifiers: &[Modifier]) -> bool {
+        match &self.keys {
+            Some(keys) => modifiers.iter().all(|m| keys.any_pressed(m.keys())),
+            None => modifiers.is_empty(),
+        }
+    }
+
+    fn pressed(&self, binding: &Binding) -> bool {
+        let input = match binding.input {
+            InputKind::Key(key) => self.keys.as_ref().is_some_and(|k| k.pressed(key)),
+            InputKind::Scan(code) => self.scans.as_ref().is_some_and(|k| k.pressed(ScanCode(code))),
+            InputKind::Mouse(button) => self.mouse.as_ref().is_some_and(|m| m.pressed(button)),
+            InputKind::Gamepad(button) => self.pads.as_ref().is_some_and(|p| p.get_pressed().any(|b| b.button_type == button)),
+            InputKind::Modifier(modifier) => self.modifiers_held(&[modifier]),
+        };
+        input && self.modifiers_held(&binding.modifiers)
+    }
+
+    /// First input pressed this frame, modifiers alone don't count
+    fn first_just_pressed(&self) -> Option<Binding> {
+        let held: Vec<Modifier> = [Modifier::Shift, Modifier::Control, Modifier::Alt].into_iter()
+            .filter(|m| self.modifiers_held(&[*m]))
+            .collect();
+        let input = self.keys.as_ref()
+            .and_then(|k| k.get_just_pressed().find(|key| !Modifier::is_modifier_key(**key)).map(|k| InputKind::Key(*k)))
+            .or_else(|| self.mouse.as_ref().and_then(|m| m.get_just_pressed().next().map(|b| InputKind::Mouse(*b))))
+            .or_else(|| self.pads.as_ref().and_then(|p| p.get_just_pressed().next().map(|b| InputKind::Gamepad(b.button_type))))?;
+        Some(Binding { input, modifiers: held })
+    }
+}
+
+fn update_action_state(settings: Res<InputSettings>,
+                       raw: RawInputs,
+                       mut state: ResMut<ActionState>) {
+    let previous = std::mem::take(&mut state.pressed);
+    state.just_pressed.clear();
+    state.just_released.clear();
+
+    let captured = state.keyboard_captured;
+    for (action, bindings) in settings.bindings.iter() {
+        if bindings.iter().filter(|b| !captured || !b.is_keyboard()).any(|b| raw.pressed(b)) {
+            state.pressed.insert(*action);
+        }
+    }
+    let pressed = state.pressed.clone();
+    state.just_pressed.extend(pressed.difference(&previous));
+    state.just_released.extend(previous.difference(&pressed));
+}
+
+/// Next input replaces binding `slot` of `action`, or is appended if there is no such slot
+#[derive(Resource, Default)]
+pub struct PendingRebind(pub Option<(Action, usize)>);
+
+/// A rebind took a binding away from other actions
+pub struct BindingConflictEvent {
+    pub binding: Binding,
+    pub kept_by: Action,
+    pub removed_from: Vec<Action>,
+}
+
+fn capture_rebind(mut pending: ResMut<PendingRebind>,
+                  raw: RawInputs,
+                  mut settings: ResMut<InputSettings>,
+                  mut conflicts: EventWriter<BindingConflictEvent>) {
+    let Some((action, slot)) = pending.0 else { return; };
+    let Some(binding) = raw.first_just_pressed() else { return; };
+    pending.0 = None;
+
+    //escape alone cancels
+    if binding == Binding::key(KeyCode::Escape) {
+        return;
+    }
+
+    let removed_from = settings.bind(action, slot, binding.clone());
+    if !removed_from.is_empty() {
+        warn!("{:?} moved to {:?}, removed from {:?}", binding, action, removed_from);
+        conflicts.send(BindingConflictEvent { binding, kept_by: action, removed_from });
+    }
+}
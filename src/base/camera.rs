@@ -1,25 +1,108 @@
-use std::cmp::max;
-use bevy::{input::{ButtonState, keyboard::KeyboardInput, mouse::MouseMotion}, prelude::*};
+use bevy::{input::mouse::MouseMotion, prelude::*};
 use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
-use bevy_mod_picking::PickingCameraBundle;
+use bevy::math::{DVec2, DVec3};
+use bevy_mod_picking::{PickingCameraBundle, Selection};
 
-use super::settings::{GameplaySettings, InputSettings};
+use crate::base::units::{Metres, SimUnits};
+use crate::space::galaxy::{GalaxyScale, Rendered, SimPosition, SolarSystem};
+
+use super::actions::{Action, ActionState};
+use super::settings::GameplaySettings;
 
 #[derive(Resource)]
 pub struct CameraID(pub Entity);
 
+/// Logarithmic zoom, every level zooms out by [`ZOOM_STEP`]
 #[derive(Resource)]
-pub struct CameraZoom(pub f64);
+pub struct CameraZoom {
+    level: f64,
+}
+
+/// Zoom factor between two levels, a scroll line moves one level
+const ZOOM_STEP: f64 = 1.15;
+/// Closest zoom, per pixel
+const MIN_ZOOM: Metres = Metres(1.0);
+/// Farthest zoom per pixel, 1920 pixels cover about 64 AU, a whole solar system out to its last planets
+const MAX_ZOOM: Metres = Metres(5.0e9);
+
+impl Default for CameraZoom {
+    fn default() -> Self {
+        Self { level: 0.0 }
+    }
+}
+
+impl CameraZoom {
+    /// Distance covered by a projected pixel
+    pub fn metres_per_pixel(&self) -> Metres {
+        Metres(ZOOM_STEP.powf(self.level))
+    }
+
+    pub fn set_metres_per_pixel(&mut self, per_pixel: Metres) {
+        let per_pixel = per_pixel.0.clamp(MIN_ZOOM.0, MAX_ZOOM.0);
+        self.level = per_pixel.ln() / ZOOM_STEP.ln();
+    }
+
+    /// Positive levels zoom out
+    pub fn zoom_by(&mut self, levels: f64) {
+        self.set_metres_per_pixel(Metres(ZOOM_STEP.powf(self.level + levels)));
+    }
+
+    /// Sim units covered by a projected pixel, projection and camera input both go through this
+    pub fn sim_per_pixel(&self, scale: &GalaxyScale) -> SimUnits {
+        scale.to_sim(self.metres_per_pixel())
+    }
+}
+
+/// Sim-space point at the center of the system view, the camera translation is folded into it every frame
+#[derive(Resource, Default)]
+pub struct CameraOrigin(pub DVec3);
+
+/// Current panning speed of the camera, eased toward the input every frame
+#[derive(Resource, Default)]
+pub struct CameraMotion {
+    pub velocity: Vec3,
+}
+
+/// Pan speed at sensitivity 1.0, in pixels/s
+const CAMERA_PAN_SPEED: f32 = 1024.0;
+/// How fast the camera reaches its pan speed and how fast it stops, in 1/s
+const CAMERA_ACCELERATION: f32 = 8.0;
+const CAMERA_DAMPING: f32 = 6.0;
+/// Trackpads scroll in pixels, this many make a wheel notch
+const SCROLL_PIXELS_PER_LINE: f64 = 100.0;
+/// How fast the camera catches up with a followed entity, in 1/s
+const FOLLOW_SMOOTHING: f32 = 5.0;
+/// Free space kept around a focused group, in pixels
+const FOCUS_MARGIN: f32 = 96.0;
+
+/// Entity the camera is locked on, panning or dragging releases it
+#[derive(Resource, Default)]
+pub struct CameraFollow {
+    pub target: Option<Entity>,
+}
 
 pub struct CameraControllerPlugin;
 
 impl Plugin for CameraControllerPlugin {
     fn build(&self, app: &mut App) {
+        app.insert_resource(CameraMotion::default());
+        app.insert_resource(CameraFollow::default());
+        app.insert_resource(CameraOrigin::default());
         app.add_startup_system(setup);
-        app.add_system(camera_input);
+        app.add_system(camera_input.label(CameraSystem::Input));
+        app.add_system(camera_follow_input.label(CameraSystem::Input));
+        app.add_system(camera_follow.label(CameraSystem::Move).after(CameraSystem::Input));
+        app.add_system(camera_focus.label(CameraSystem::Move).after(CameraSystem::Input));
     }
 }
 
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum CameraSystem {
+    Input,
+    /// Anything reading the camera position should run after this
+    Move,
+}
+
 fn setup(mut commands: Commands) {
     let id = commands.spawn((
         Camera2dBundle::default(),
@@ -27,87 +110,188 @@ fn setup(mut commands: Commands) {
     )).id();
 
     commands.insert_resource(CameraID(id));
-    commands.insert_resource(CameraZoom(1.0));
+    commands.insert_resource(CameraZoom::default());
 }
 
 
 fn camera_input(time: Res<Time>,
                 mut camera_zoom: ResMut<CameraZoom>,
-                mut camera_query: Query<(&mut Transform), With<Camera>>,
-                keys: Res<Input<ScanCode>>,
+                scale: Res<GalaxyScale>,
+                mut motion: ResMut<CameraMotion>,
+                mut follow: ResMut<CameraFollow>,
+                mut camera_query: Query<&mut Transform, With<Camera>>,
+                actions: Res<ActionState>,
                 camera_id: Res<CameraID>,
                 settings: Res<GameplaySettings>,
-                input_settings: Res<InputSettings>,
+                windows: Res<Windows>,
                 mut motion_evr: EventReader<MouseMotion>,
-                mut scroll_evr: EventReader<MouseWheel>,
-                buttons: Res<Input<MouseButton>>) {
+                mut scroll_evr: EventReader<MouseWheel>) {
 
     //get the camera first before checking inputs
     //also check if unique
     let got = camera_query.get_mut(camera_id.0);
-    match got {
-        Ok(mut tr) => {
-            for ev in scroll_evr.iter() {
-                match ev.unit {
-                    MouseScrollUnit::Line => {
-                        camera_zoom.0 = f64::max((camera_zoom.0+ev.y as f64),1.0) ;
-                        println!("scale {:?}", camera_zoom.0);
-                    }
-                    MouseScrollUnit::Pixel => {
-                        println!("Scroll (pixel units): vertical: {}, horizontal: {}", ev.y, ev.x);
-                    }
-                }
-            }
+    if let Ok(mut tr) = got {
+        let lines: f64 = scroll_evr.iter().map(|ev| match ev.unit {
+            MouseScrollUnit::Line => ev.y as f64,
+            MouseScrollUnit::Pixel => ev.y as f64 / SCROLL_PIXELS_PER_LINE,
+        }).sum();
+        if lines != 0.0 {
+            //keep the world point under the cursor in place, the screen center if there is no cursor
+            let cursor = windows.get_primary()
+                .and_then(|w| w.cursor_position().map(|c| c - Vec2::new(w.width(), w.height()) / 2.0))
+                .unwrap_or(Vec2::ZERO);
+            let before = camera_zoom.sim_per_pixel(&scale).0;
+            let anchor = (tr.translation.truncate() + cursor).as_dvec2() * before;
+            //scrolling up zooms in
+            camera_zoom.zoom_by(-lines);
+            let anchored = anchor / camera_zoom.sim_per_pixel(&scale).0;
+            tr.translation.x = (anchored.x - cursor.x as f64) as f32;
+            tr.translation.y = (anchored.y - cursor.y as f64) as f32;
+        }
 
-            let mut dir = Vec3::ZERO;
-            //mouse drag
-            /*
-            if buttons.pressed(MouseButton::Left) {
-                for ev in motion_evr.iter() {
-                    dir += Vec3 {
-                        x: -ev.delta.x,
-                        y: ev.delta.y,
-                        z: 0.0,
-                    }
-                }
-            }
-            */
+        //mouse drag moves the camera 1:1 with the cursor, no inertia
+        if actions.pressed(Action::CameraDrag) {
+            let delta: Vec2 = motion_evr.iter().map(|ev| Vec2::new(-ev.delta.x, ev.delta.y)).sum();
+            pan(&mut tr, &mut follow, delta);
+            motion.velocity = Vec3::ZERO;
+            return;
+        }
 
-            //keyboard
-            match input_settings.camera_up {
-                Some(code) => {
-                    if keys.pressed(code) {
-                        dir += Vec3::Y;
-                    }
-                }
-                None => {}
-            }
-            match input_settings.camera_down {
-                Some(code) => {
-                    if keys.pressed(code) {
-                        dir += Vec3::NEG_Y;
-                    }
-                }
-                None => {}
-            }
-            match input_settings.camera_right {
-                Some(code) => {
-                    if keys.pressed(code) {
+        let mut dir = Vec3::ZERO;
+
+        //keyboard
+        if actions.pressed(Action::CameraUp) {
+            dir += Vec3::Y;
+        }
+        if actions.pressed(Action::CameraDown) {
+            dir += Vec3::NEG_Y;
+        }
+        if actions.pressed(Action::CameraRight) {
+            dir += Vec3::X;
+        }
+        if actions.pressed(Action::CameraLeft) {
+            dir += Vec3::NEG_X;
+        }
+
+        //screen edges
+        if settings.camera_edge_pan {
+            if let Some(window) = windows.get_primary() {
+                if let Some(cursor) = window.cursor_position() {
+                    let margin = settings.camera_edge_margin;
+                    if cursor.x <= margin {
+                        dir += Vec3::NEG_X;
+                    } else if cursor.x >= window.width() - margin {
                         dir += Vec3::X;
                     }
-                }
-                None => {}
-            }
-            match input_settings.camera_left {
-                Some(code) => {
-                    if keys.pressed(code) {
-                        dir += Vec3::NEG_X;
+                    if cursor.y <= margin {
+                        dir += Vec3::NEG_Y;
+                    } else if cursor.y >= window.height() - margin {
+                        dir += Vec3::Y;
                     }
                 }
-                None => {}
             }
-            tr.translation += dir * 512.0 * time.delta_seconds()
         }
-        Err(_) => {}
+
+        //camera translation is in projected pixels, a pixel covers more space the more we zoom out
+        //so a constant screen speed already pans faster across the world when zoomed out
+        let target = dir.normalize_or_zero() * pan_speed(&settings);
+        let rate = if dir == Vec3::ZERO { CAMERA_DAMPING } else { CAMERA_ACCELERATION };
+        let blend = 1.0 - (-rate * time.delta_seconds()).exp();
+        motion.velocity = motion.velocity.lerp(target, blend);
+        if motion.velocity.length_squared() < 0.01 {
+            motion.velocity = Vec3::ZERO;
+        }
+
+        pan(&mut tr, &mut follow, motion.velocity.truncate() * time.delta_seconds());
+    }
+}
+
+/// Moving the camera by hand stops following
+fn pan(camera: &mut Transform, follow: &mut CameraFollow, delta: Vec2) {
+    if delta != Vec2::ZERO {
+        follow.target = None;
+        camera.translation += delta.extend(0.0);
+    }
+}
+
+/// Screen pixels per second at full speed
+fn pan_speed(settings: &GameplaySettings) -> f32 {
+    CAMERA_PAN_SPEED * settings.camera_keyboard_sensivity
+}
+
+/// Toggle following the first selected entity
+fn camera_follow_input(actions: Res<ActionState>,
+                       mut follow: ResMut<CameraFollow>,
+                       selected: Query<(Entity, &Selection), With<Transform>>) {
+    if actions.just_pressed(Action::CameraFollow) {
+        follow.target = match follow.target {
+            Some(_) => None,
+            None => selected.iter().find(|(_, s)| s.selected()).map(|(e, _)| e),
+        };
     }
-}
\ No newline at end of file
+}
+
+/// Ease the camera toward the projected position of the followed entity
+fn camera_follow(time: Res<Time>,
+                 mut follow: ResMut<CameraFollow>,
+                 camera_id: Res<CameraID>,
+                 mut cameras: Query<&mut Transform, With<Camera>>,
+                 targets: Query<&Transform, Without<Camera>>) {
+    let Some(target) = follow.target else { return; };
+    let Ok(target) = targets.get(target) else {
+        follow.target = None;
+        return;
+    };
+    if let Ok(mut camera) = cameras.get_mut(camera_id.0) {
+        let goal = target.translation.truncate();
+        let blend = 1.0 - (-FOLLOW_SMOOTHING * time.delta_seconds()).exp();
+        let pos = camera.translation.truncate().lerp(goal, blend);
+        camera.translation.x = pos.x;
+        camera.translation.y = pos.y;
+    }
+}
+
+/// Center and zoom so the selected entities, or everything shown if nothing is selected, fit the screen
+fn camera_focus(actions: Res<ActionState>,
+                scale: Res<GalaxyScale>,
+                windows: Res<Windows>,
+                camera_id: Res<CameraID>,
+                mut zoom: ResMut<CameraZoom>,
+                mut follow: ResMut<CameraFollow>,
+                mut origin: ResMut<CameraOrigin>,
+                mut cameras: Query<&mut Transform, With<Camera>>,
+                shown: Query<(&SimPosition, Option<&Selection>), (With<Rendered>, Without<SolarSystem>)>) {
+    if !actions.just_pressed(Action::CameraFocus) {
+        return;
+    }
+    let mut group: Vec<DVec2> = shown.iter()
+        .filter(|(_, s)| s.is_some_and(|s| s.selected()))
+        .map(|(p, _)| p.0.truncate())
+        .collect();
+    if group.is_empty() {
+        group = shown.iter().map(|(p, _)| p.0.truncate()).collect();
+    }
+    if group.is_empty() {
+        return;
+    }
+
+    let min = group.iter().fold(DVec2::splat(f64::MAX), |a, b| a.min(*b));
+    let max = group.iter().fold(DVec2::splat(f64::MIN), |a, b| a.max(*b));
+    let Some(window) = windows.get_primary() else { return; };
+    let view = DVec2::new(
+        (window.width() - 2.0 * FOCUS_MARGIN).max(1.0) as f64,
+        (window.height() - 2.0 * FOCUS_MARGIN).max(1.0) as f64,
+    );
+    let extent = max - min;
+    let per_pixel = SimUnits((extent.x / view.x).max(extent.y / view.y));
+    zoom.set_metres_per_pixel(scale.to_metres(per_pixel));
+
+    if let Ok(mut camera) = cameras.get_mut(camera_id.0) {
+        let center = (min + max) / 2.0;
+        origin.0.x = center.x;
+        origin.0.y = center.y;
+        camera.translation.x = 0.0;
+        camera.translation.y = 0.0;
+    }
+    follow.target = None;
+}
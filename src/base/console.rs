@@ -0,0 +1,460 @@
+//! Developer console : plugins register commands in [`ConsoleCommands`], lines typed in the
+//! overlay or read from stdin in headless runs are parsed against them and run with the world.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::BufRead;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use super::actions::{Action, ActionState};
+
+/// Lines of output kept in the overlay
+const OUTPUT_KEPT: usize = 200;
+const HISTORY_KEPT: usize = 100;
+
+/// Registry, parsing and execution, without any display
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        let mut commands = ConsoleCommands::default();
+        commands.register(ConsoleCommand::new("help", "list commands, or the usage of one", help_command)
+            .arg("command", ArgKind::Word).optional());
+        app
+            .insert_resource(commands)
+            .insert_resource(ConsoleState::default())
+            //exclusive systems can't be ordered after regular ones, at the end it follows input and stdin
+            .add_system_to_stage(CoreStage::PreUpdate, run_console_commands
+                .at_end()
+                .label(ConsoleSystem::Run));
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ConsoleSystem {
+    Read,
+    Run,
+}
+
+/// Text overlay toggled with [`Action::ToggleConsole`]
+pub struct ConsoleOverlayPlugin;
+
+impl Plugin for ConsoleOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        super::ensure_egui(app);
+        app.add_system(console_overlay);
+    }
+}
+
+/// Reads commands from stdin, one per line, and prints their output
+pub struct ConsoleStdinPlugin;
+
+impl Plugin for ConsoleStdinPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break; };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        app
+            .insert_resource(StdinLines(Mutex::new(receiver)))
+            .add_system_to_stage(CoreStage::PreUpdate, read_stdin_lines
+                .label(ConsoleSystem::Read));
+        app.world.resource_mut::<ConsoleState>().echo = true;
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ArgKind {
+    Int,
+    Float,
+    /// `12v0` as printed by the console, or a bare index for generation 0
+    Entity,
+    Word,
+    Choice(&'static [&'static str]),
+}
+
+#[derive(Debug, Clone)]
+pub struct ArgSpec {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    Int(i64),
+    Float(f64),
+    Entity(Entity),
+    Word(String),
+}
+
+/// Parsed arguments, in the order of the command's [`ArgSpec`]s, optional ones may be missing
+#[derive(Debug, Default)]
+pub struct Args(pub Vec<ArgValue>);
+
+impl Args {
+    pub fn int(&self, i: usize) -> Option<i64> {
+        match self.0.get(i) {
+            Some(ArgValue::Int(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Integers are accepted where floats are expected
+    pub fn float(&self, i: usize) -> Option<f64> {
+        match self.0.get(i) {
+            Some(ArgValue::Float(v)) => Some(*v),
+            Some(ArgValue::Int(v)) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    pub fn entity(&self, i: usize) -> Option<Entity> {
+        match self.0.get(i) {
+            Some(ArgValue::Entity(e)) => Some(*e),
+            _ => None,
+        }
+    }
+
+    pub fn word(&self, i: usize) -> Option<&str> {
+        match self.0.get(i) {
+            Some(ArgValue::Word(w)) => Some(w),
+            _ => None,
+        }
+    }
+}
+
+/// Output on success, message on failure
+pub type CommandResult = Result<String, String>;
+pub type CommandHandler = fn(&mut World, &Args) -> CommandResult;
+
+#[derive(Clone)]
+pub struct ConsoleCommand {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub args: Vec<ArgSpec>,
+    pub handler: CommandHandler,
+}
+
+impl ConsoleCommand {
+    pub fn new(name: &'static str, help: &'static str, handler: CommandHandler) -> Self {
+        Self { name, help, args: Vec::new(), handler }
+    }
+
+    pub fn arg(mut self, name: &'static str, kind: ArgKind) -> Self {
+        self.args.push(ArgSpec { name, kind, optional: false });
+        self
+    }
+
+    /// Makes the last argument optional, every argument after it has to be optional too
+    pub fn optional(mut self) -> Self {
+        if let Some(arg) = self.args.last_mut() {
+            arg.optional = true;
+        }
+        self
+    }
+
+    pub fn usage(&self) -> String {
+        let mut usage = String::from(self.name);
+        for arg in self.args.iter() {
+            let name = match arg.kind {
+                ArgKind::Choice(choices) => choices.join("|"),
+                _ => arg.name.to_string(),
+            };
+            if arg.optional {
+                usage += &format!(" [{}]", name);
+            } else {
+                usage += &format!(" <{}>", name);
+            }
+        }
+        usage
+    }
+
+    fn parse_args(&self, words: &[String]) -> Result<Args, String> {
+        let required = self.args.iter().filter(|a| !a.optional).count();
+        if words.len() < required || words.len() > self.args.len() {
+            return Err(format!("usage : {}", self.usage()));
+        }
+        let values = self.args.iter().zip(words.iter()).map(|(spec, word)| {
+            parse_arg(spec.kind, word).ok_or_else(|| format!("{} : invalid {:?} '{}'", spec.name, spec.kind, word))
+        });
+        values.collect::<Result<Vec<_>, _>>().map(Args)
+    }
+}
+
+fn parse_arg(kind: ArgKind, word: &str) -> Option<ArgValue> {
+    match kind {
+        ArgKind::Int => word.parse().ok().map(ArgValue::Int),
+        ArgKind::Float => word.parse().ok().map(ArgValue::Float),
+        ArgKind::Entity => parse_entity(word).map(ArgValue::Entity),
+        ArgKind::Word => Some(ArgValue::Word(word.to_string())),
+        ArgKind::Choice(choices) => choices.iter()
+            .find(|c| c.eq_ignore_ascii_case(word))
+            .map(|c| ArgValue::Word(c.to_string())),
+    }
+}
+
+fn parse_entity(word: &str) -> Option<Entity> {
+    let (index, generation) = match word.split_once('v') {
+        Some((index, generation)) => (index.parse::<u32>().ok()?, generation.parse::<u32>().ok()?),
+        None => (word.parse::<u32>().ok()?, 0),
+    };
+    Some(Entity::from_bits((generation as u64) << 32 | index as u64))
+}
+
+/// Words of a line, double quotes keep spaces
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// Every command the console knows, by name
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(BTreeMap<&'static str, ConsoleCommand>);
+
+impl ConsoleCommands {
+    /// A command with the same name is replaced
+    pub fn register(&mut self, command: ConsoleCommand) {
+        self.0.insert(command.name, command);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ConsoleCommand> {
+        self.0.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&ConsoleCommand> {
+        self.0.values()
+    }
+
+    pub fn parse(&self, line: &str) -> Result<(CommandHandler, Args), String> {
+        let words = split_words(line);
+        let Some((name, args)) = words.split_first() else { return Err(String::new()); };
+        let command = self.get(name).ok_or_else(|| format!("unknown command '{}', try help", name))?;
+        Ok((command.handler, command.parse_args(args)?))
+    }
+
+    /// Candidates for the word being typed at the end of `line`
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let words = split_words(line);
+        let typing_new_word = line.is_empty() || line.ends_with(char::is_whitespace);
+        let prefix = if typing_new_word { "" } else { words.last().map_or("", |w| w.as_str()) };
+        let position = if typing_new_word { words.len() } else { words.len().saturating_sub(1) };
+
+        if position == 0 {
+            return self.0.keys().filter(|n| n.starts_with(prefix)).map(|n| n.to_string()).collect();
+        }
+        let Some(spec) = self.get(&words[0]).and_then(|c| c.args.get(position - 1)) else { return Vec::new(); };
+        match spec.kind {
+            ArgKind::Choice(choices) => choices.iter()
+                .filter(|c| c.starts_with(prefix))
+                .map(|c| c.to_string())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ConsoleState {
+    pub open: bool,
+    pub input: String,
+    /// Lines waiting to run, in order
+    pub pending: Vec<String>,
+    pub output: VecDeque<String>,
+    history: Vec<String>,
+    /// Entry of `history` being browsed
+    history_cursor: Option<usize>,
+    /// Also print output, for headless runs
+    echo: bool,
+}
+
+impl ConsoleState {
+    pub fn submit(&mut self, line: impl Into<String>) {
+        let line = line.into();
+        if line.trim().is_empty() {
+            return;
+        }
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+            if self.history.len() > HISTORY_KEPT {
+                self.history.remove(0);
+            }
+        }
+        self.history_cursor = None;
+        self.pending.push(line);
+    }
+
+    pub fn print(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        if self.echo {
+            println!("{}", text);
+        }
+        self.output.extend(text.lines().map(String::from));
+        while self.output.len() > OUTPUT_KEPT {
+            self.output.pop_front();
+        }
+    }
+
+    /// Walk the history, `older` goes back in time
+    fn browse_history(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() - 1;
+        self.history_cursor = match (self.history_cursor, older) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i < last => Some(i + 1),
+            (Some(_), false) => None,
+        };
+        self.input = self.history_cursor.map_or(String::new(), |i| self.history[i].clone());
+    }
+}
+
+fn help_command(world: &mut World, args: &Args) -> CommandResult {
+    let commands = world.resource::<ConsoleCommands>();
+    match args.word(0) {
+        Some(name) => commands.get(name)
+            .map(|c| format!("{}\n  {}", c.usage(), c.help))
+            .ok_or_else(|| format!("unknown command '{}'", name)),
+        None => Ok(commands.iter().map(|c| format!("{} : {}", c.usage(), c.help)).collect::<Vec<_>>().join("\n")),
+    }
+}
+
+/// Runs with the whole world so commands can touch anything
+fn run_console_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<ConsoleState>().pending);
+    for line in pending {
+        let parsed = world.resource::<ConsoleCommands>().parse(&line);
+        let result = parsed.and_then(|(handler, args)| handler(world, &args));
+        let mut state = world.resource_mut::<ConsoleState>();
+        state.print(format!("> {}", line));
+        match result {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => state.print(output),
+            Err(message) => state.print(format!("error : {}", message)),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct StdinLines(Mutex<Receiver<String>>);
+
+fn read_stdin_lines(lines: Res<StdinLines>,
+                    mut state: ResMut<ConsoleState>) {
+    let Ok(receiver) = lines.0.lock() else { return; };
+    while let Ok(line) = receiver.try_recv() {
+        state.submit(line);
+    }
+}
+
+fn console_overlay(mut egui: ResMut<EguiContext>,
+                   mut actions: ResMut<ActionState>,
+                   commands: Res<ConsoleCommands>,
+                   mut state: ResMut<ConsoleState>) {
+    let toggled = actions.just_pressed(Action::ToggleConsole);
+    if toggled {
+        state.open = !state.open;
+    }
+    actions.keyboard_captured = false;
+    if !state.open {
+        return;
+    }
+
+    let ctx = egui.ctx_mut();
+    let (enter, tab, up, down, escape) = {
+        let input = ctx.input();
+        (input.key_pressed(egui::Key::Enter), input.key_pressed(egui::Key::Tab),
+         input.key_pressed(egui::Key::ArrowUp), input.key_pressed(egui::Key::ArrowDown),
+         input.key_pressed(egui::Key::Escape))
+    };
+    let mut open = state.open;
+    egui::Window::new("Console")
+        .open(&mut open)
+        .default_width(600.0)
+        .resizable(true)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in state.output.iter() {
+                        ui.monospace(line);
+                    }
+                });
+
+            let before = state.input.clone();
+            let edit = ui.add(egui::TextEdit::singleline(&mut state.input)
+                .font(egui::TextStyle::Monospace)
+                .lock_focus(true)
+                .desired_width(f32::INFINITY));
+            //the text of whatever key opened the console would otherwise end up in the line
+            if toggled {
+                state.input = before;
+            }
+
+            let candidates = commands.complete(&state.input);
+            if edit.has_focus() || edit.lost_focus() {
+                if enter {
+                    let line = std::mem::take(&mut state.input);
+                    state.submit(line);
+                } else if tab && !candidates.is_empty() {
+                    let words = split_words(&state.input);
+                    let keep = if state.input.ends_with(' ') { words.len() } else { words.len().saturating_sub(1) };
+                    let mut line = words[..keep].join(" ");
+                    if !line.is_empty() {
+                        line.push(' ');
+                    }
+                    line += &common_prefix(&candidates);
+                    if candidates.len() == 1 {
+                        line.push(' ');
+                    }
+                    state.input = line;
+                } else if up {
+                    state.browse_history(true);
+                } else if down {
+                    state.browse_history(false);
+                }
+            }
+            edit.request_focus();
+            if !candidates.is_empty() && !state.input.is_empty() {
+                ui.weak(candidates.join("  "));
+            }
+        });
+    state.open = open && !escape;
+    actions.keyboard_captured = state.open;
+}
+
+fn common_prefix(words: &[String]) -> String {
+    let Some(first) = words.first() else { return String::new(); };
+    let mut len = first.len();
+    for word in words.iter().skip(1) {
+        len = len.min(first.bytes().zip(word.bytes()).take_while(|(a, b)| a == b).count());
+    }
+    first[..len].to_string()
+}
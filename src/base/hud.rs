@@ -0,0 +1,31 @@
+//! Always-on readouts drawn over the game.
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use super::timer::SimClock;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        super::ensure_egui(app);
+        app.add_system(sim_clock_readout);
+    }
+}
+
+/// Game date and speed, top right
+fn sim_clock_readout(mut egui: ResMut<EguiContext>,
+                     clock: Option<Res<SimClock>>) {
+    let Some(clock) = clock else { return; };
+    let speed = if clock.paused && clock.fixed_step.is_none() {
+        String::from("paused")
+    } else {
+        format!("x{}", clock.time_scale)
+    };
+    egui::Area::new("sim_clock")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8.0, 8.0))
+        .show(egui.ctx_mut(), |ui| {
+            ui.monospace(format!("{}  {}", clock.date(), speed));
+        });
+}
@@ -0,0 +1,49 @@
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+/// Every random draw of the simulation goes through this, so a seed and the orders replay a run exactly
+#[derive(Resource)]
+pub struct SimRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Default for SimRng {
+    fn default() -> Self {
+        Self::seeded(rand::random())
+    }
+}
+
+impl SimRng {
+    pub fn seeded(seed: u64) -> Self {
+        Self { seed, rng: StdRng::seed_from_u64(seed) }
+    }
+
+    /// Seed of the current sequence
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        *self = Self::seeded(seed);
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
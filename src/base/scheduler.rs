@@ -0,0 +1,218 @@
+//! Named jobs running at an interval of sim or real time. Plugins add their jobs to the
+//! [`Scheduler`] when they are built and gate systems on them with [`job_due`].
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+
+use super::console::{ArgKind, Args, CommandResult, ConsoleCommand, ConsoleCommands};
+use super::timer::SimClock;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum JobClock {
+    /// Follows [`SimClock`], stops on pause and speeds up with it
+    Sim,
+    Real,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Job {
+    pub clock: JobClock,
+    pub interval: Duration,
+    pub repeat: bool,
+    /// Each run is split over this many frames, see [`JobSlot`]
+    pub slots: u32,
+}
+
+impl Job {
+    pub fn every(clock: JobClock, interval: Duration) -> Self {
+        Self { clock, interval, repeat: true, slots: 1 }
+    }
+
+    pub fn once(clock: JobClock, delay: Duration) -> Self {
+        Self { clock, interval: delay, repeat: false, slots: 1 }
+    }
+
+    /// Spread the work of each run over `slots` frames evenly spaced in the interval
+    pub fn staggered(mut self, slots: u32) -> Self {
+        self.slots = slots.max(1);
+        self
+    }
+
+    fn slot_interval(&self) -> Duration {
+        self.interval / self.slots
+    }
+}
+
+/// Part of a staggered job due this frame
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct JobSlot {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl JobSlot {
+    /// Whether the item with this key is handled in this slot. The key has to stay
+    /// the same across saves, like a pilot uid, for replays to be deterministic.
+    pub fn includes(&self, key: u64) -> bool {
+        key % self.count as u64 == self.index as u64
+    }
+}
+
+struct JobState {
+    job: Job,
+    next_run: Duration,
+    next_slot: u32,
+    runs: u64,
+    due: Option<JobSlot>,
+    finished: bool,
+}
+
+/// Introspection of a job
+#[derive(Debug, Clone)]
+pub struct JobInfo {
+    pub name: &'static str,
+    pub job: Job,
+    /// Time left before the next slot runs, on the job's clock
+    pub next_in: Duration,
+    pub runs: u64,
+}
+
+#[derive(Resource, Default)]
+pub struct Scheduler {
+    jobs: BTreeMap<&'static str, JobState>,
+    sim_now: Duration,
+    real_now: Duration,
+}
+
+impl Scheduler {
+    /// A job with the same name is replaced
+    pub fn add(&mut self, name: &'static str, job: Job) {
+        let next_run = self.now(job.clock) + job.slot_interval();
+        self.jobs.insert(name, JobState { job, next_run, next_slot: 0, runs: 0, due: None, finished: false });
+    }
+
+    pub fn cancel(&mut self, name: &str) {
+        self.jobs.remove(name);
+    }
+
+    /// Whether any slot of the job runs this frame
+    pub fn is_due(&self, name: &str) -> bool {
+        self.slot(name).is_some()
+    }
+
+    pub fn slot(&self, name: &str) -> Option<JobSlot> {
+        self.jobs.get(name).and_then(|j| j.due)
+    }
+
+    /// Time left before the job runs again, `None` for unknown or finished jobs
+    pub fn next_run(&self, name: &str) -> Option<Duration> {
+        let state = self.jobs.get(name).filter(|j| !j.finished)?;
+        Some(state.next_run.saturating_sub(self.now(state.job.clock)))
+    }
+
+    pub fn jobs(&self) -> impl Iterator<Item=JobInfo> + '_ {
+        self.jobs.iter().filter(|(_, j)| !j.finished).map(|(name, state)| JobInfo {
+            name,
+            job: state.job,
+            next_in: state.next_run.saturating_sub(self.now(state.job.clock)),
+            runs: state.runs,
+        })
+    }
+
+    /// Start the jobs of a clock over from `now`, when that clock jumped like on load
+    pub fn restart(&mut self, clock: JobClock, now: Duration) {
+        match clock {
+            JobClock::Sim => self.sim_now = now,
+            JobClock::Real => self.real_now = now,
+        }
+        for state in self.jobs.values_mut().filter(|j| j.job.clock == clock) {
+            state.next_run = now + state.job.slot_interval();
+            state.next_slot = 0;
+            state.due = None;
+        }
+    }
+
+    fn now(&self, clock: JobClock) -> Duration {
+        match clock {
+            JobClock::Sim => self.sim_now,
+            JobClock::Real => self.real_now,
+        }
+    }
+
+    fn update(&mut self, sim_now: Duration, real_now: Duration) {
+        self.sim_now = sim_now;
+        self.real_now = real_now;
+        self.jobs.retain(|_, j| !j.finished);
+        for state in self.jobs.values_mut() {
+            let now = match state.job.clock {
+                JobClock::Sim => sim_now,
+                JobClock::Real => real_now,
+            };
+            state.due = None;
+            if now < state.next_run {
+                continue;
+            }
+
+            state.due = Some(JobSlot { index: state.next_slot, count: state.job.slots });
+            state.next_slot = (state.next_slot + 1) % state.job.slots;
+            if state.next_slot == 0 {
+                state.runs += 1;
+                state.finished = !state.job.repeat;
+            }
+            state.next_run += state.job.slot_interval();
+            //late jobs run once and start over instead of catching up
+            if state.next_run <= now {
+                state.next_run = now + state.job.slot_interval();
+            }
+        }
+    }
+}
+
+/// Run criteria letting a system run only on frames where the job is due
+pub fn job_due(name: &'static str) -> impl FnMut(Option<Res<Scheduler>>) -> ShouldRun {
+    move |scheduler: Option<Res<Scheduler>>| {
+        if scheduler.is_some_and(|s| s.is_due(name)) {
+            ShouldRun::Yes
+        } else {
+            ShouldRun::No
+        }
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SchedulerSystem {
+    Update,
+}
+
+pub(super) fn update_scheduler(time: Res<Time>,
+                               clock: Res<SimClock>,
+                               mut scheduler: ResMut<Scheduler>) {
+    scheduler.update(clock.elapsed(), time.elapsed());
+}
+
+pub(super) fn register_jobs_command(commands: &mut ConsoleCommands) {
+    commands.register(ConsoleCommand::new("jobs", "list scheduled jobs and when they run next", list_jobs)
+        .arg("name", ArgKind::Word).optional());
+}
+
+fn list_jobs(world: &mut World, args: &Args) -> CommandResult {
+    let scheduler = world.resource::<Scheduler>();
+    let lines: Vec<String> = scheduler.jobs()
+        .filter(|info| args.word(0).is_none_or(|name| info.name.contains(name)))
+        .map(|info| format!("{} : {:?} {} {:.1}s{}, next in {:.2}s, ran {} times",
+                            info.name,
+                            info.job.clock,
+                            if info.job.repeat { "every" } else { "once after" },
+                            info.job.interval.as_secs_f64(),
+                            if info.job.slots > 1 { format!(" over {} slots", info.job.slots) } else { String::new() },
+                            info.next_in.as_secs_f64(),
+                            info.runs))
+        .collect();
+    if lines.is_empty() {
+        return Ok(String::from("no job"));
+    }
+    Ok(lines.join("\n"))
+}
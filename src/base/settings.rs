@@ -1,43 +1,255 @@
-use std::default;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::actions::{Action, Binding, Modifier};
+use crate::space::overview::OverviewPreset;
+use crate::space::project::OffscreenMode;
+
+const SETTINGS_FILE: &str = "settings.ron";
 
 pub struct GameSettingsPlugin;
 
 impl Plugin for GameSettingsPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(GameplaySettings::default());
-        app.insert_resource(InputSettings::default());
+        let path = settings_path();
+        let file = match path.as_deref() {
+            Some(path) => SettingsFile::load(path),
+            None => {
+                warn!("no user config directory, settings won't be saved");
+                SettingsFile::default()
+            }
+        };
+        let (gameplay, input) = file.into_settings();
+
+        app.insert_resource(gameplay);
+        app.insert_resource(input);
+        app.insert_resource(SettingsPath(path));
+        app.add_system_to_stage(CoreStage::Last, save_changed_settings);
     }
 }
 
-
+/// Where settings are read from and written to, `None` when there is no config directory
 #[derive(Resource)]
+pub struct SettingsPath(pub Option<PathBuf>);
+
+fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("saloon").join(SETTINGS_FILE))
+}
+
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GameplaySettings {
     pub camera_keyboard_sensivity: f32,
+    /// Pan when the cursor touches the window borders
+    pub camera_edge_pan: bool,
+    /// Width of the border area, in pixels
+    pub camera_edge_margin: f32,
+    pub offscreen_mode: OffscreenMode,
+    /// Distance between edge indicators and the window borders, in pixels
+    pub offscreen_margin: f32,
+    /// Filters offered by the system overview, in order
+    pub overview_presets: Vec<OverviewPreset>,
 }
 
 impl Default for GameplaySettings {
     fn default() -> Self {
-        Self { camera_keyboard_sensivity: 0.5 }
+        Self {
+            camera_keyboard_sensivity: 0.5,
+            camera_edge_pan: true,
+            camera_edge_margin: 8.0,
+            offscreen_mode: OffscreenMode::EdgeIndicator,
+            offscreen_margin: 24.0,
+            overview_presets: OverviewPreset::defaults(),
+        }
     }
 }
 
-#[derive(Resource)]
+impl GameplaySettings {
+    /// Clamp out of range values, returns what had to be fixed
+    pub fn validate(&mut self) -> Vec<String> {
+        let mut fixed = Vec::new();
+        if !(0.05..=5.0).contains(&self.camera_keyboard_sensivity) {
+            fixed.push(format!("camera_keyboard_sensivity {} out of [0.05, 5.0]", self.camera_keyboard_sensivity));
+            self.camera_keyboard_sensivity = if self.camera_keyboard_sensivity.is_nan() {
+                Self::default().camera_keyboard_sensivity
+            } else {
+                self.camera_keyboard_sensivity.clamp(0.05, 5.0)
+            };
+        }
+        if !(0.0..=200.0).contains(&self.camera_edge_margin) {
+            fixed.push(format!("camera_edge_margin {} out of [0, 200]", self.camera_edge_margin));
+            self.camera_edge_margin = Self::default().camera_edge_margin;
+        }
+        if !(0.0..=200.0).contains(&self.offscreen_margin) {
+            fixed.push(format!("offscreen_margin {} out of [0, 200]", self.offscreen_margin));
+            self.offscreen_margin = Self::default().offscreen_margin;
+        }
+        if self.overview_presets.is_empty() {
+            fixed.push(String::from("overview_presets is empty"));
+            self.overview_presets = OverviewPreset::defaults();
+        }
+        fixed
+    }
+}
+
+/// Bindings of every [`Action`], an action can have several bindings
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct InputSettings {
-    pub camera_up: Option<ScanCode>,
-    pub camera_down: Option<ScanCode>,
-    pub camera_left: Option<ScanCode>,
-    pub camera_right: Option<ScanCode>,
+    pub bindings: HashMap<Action, Vec<Binding>>,
 }
 
 impl Default for InputSettings {
     fn default() -> Self {
         Self {
-            camera_up: Some(ScanCode(17)),
-            camera_down: Some(ScanCode(31)),
-            camera_left: Some(ScanCode(30)),
-            camera_right: Some(ScanCode(32)),
+            bindings: HashMap::from([
+                (Action::CameraUp, vec![Binding::scan(17)]),
+                (Action::CameraDown, vec![Binding::scan(31)]),
+                (Action::CameraLeft, vec![Binding::scan(30)]),
+                (Action::CameraRight, vec![Binding::scan(32)]),
+                (Action::CameraDrag, vec![Binding::mouse(MouseButton::Middle)]),
+                (Action::CameraFollow, vec![Binding::key(KeyCode::F)]),
+                (Action::CameraFocus, vec![Binding::key(KeyCode::Home)]),
+                (Action::ExitSystemView, vec![Binding::key(KeyCode::Numpad0), Binding::key(KeyCode::Escape)]),
+                (Action::QuickSave, vec![Binding::key(KeyCode::F5)]),
+                (Action::ToggleConsole, vec![Binding::key(KeyCode::Grave)]),
+                (Action::PauseSim, vec![Binding::key(KeyCode::Space)]),
+                (Action::SimFaster, vec![Binding::key(KeyCode::Equals), Binding::key(KeyCode::NumpadAdd)]),
+                (Action::SimSlower, vec![Binding::key(KeyCode::Minus), Binding::key(KeyCode::NumpadSubtract)]),
+                (Action::ContextMenu, vec![Binding::mouse(MouseButton::Right)]),
+                (Action::Select, vec![Binding::mouse(MouseButton::Left)]),
+                (Action::AddToSelection, vec![Binding::modifier(Modifier::Shift), Binding::modifier(Modifier::Control)]),
+            ]),
+        }
+    }
+}
+
+impl InputSettings {
+    /// Set binding `slot` of `action`, appending when the slot doesn't exist yet.
+    /// The binding is removed from any other action, those are returned.
+    pub fn bind(&mut self, action: Action, slot: usize, binding: Binding) -> Vec<Action> {
+        let mut removed_from = Vec::new();
+        for (other, bindings) in self.bindings.iter_mut() {
+            if *other != action && bindings.contains(&binding) {
+                bindings.retain(|b| *b != binding);
+                removed_from.push(*other);
+            }
+        }
+
+        let bindings = self.bindings.entry(action).or_default();
+        if bindings.contains(&binding) {
+            return removed_from;
+        }
+        match bindings.get_mut(slot) {
+            Some(existing) => *existing = binding,
+            None => bindings.push(binding),
         }
+        removed_from
+    }
+
+    pub fn unbind(&mut self, action: Action, slot: usize) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            if slot < bindings.len() {
+                bindings.remove(slot);
+            }
+        }
+    }
+
+    /// Bindings shared by more than one action
+    pub fn conflicts(&self) -> Vec<(Binding, Vec<Action>)> {
+        let mut users: HashMap<&Binding, Vec<Action>> = HashMap::new();
+        for (action, bindings) in self.bindings.iter() {
+            for binding in bindings.iter() {
+                users.entry(binding).or_default().push(*action);
+            }
+        }
+        users.into_iter()
+            .filter(|(_, actions)| actions.len() > 1)
+            .map(|(binding, actions)| (binding.clone(), actions))
+            .collect()
+    }
+}
+
+/// Everything persisted in the settings file, missing fields take their default value
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SettingsFile {
+    gameplay: GameplaySettings,
+    input: InputSettings,
+}
+
+impl SettingsFile {
+    /// Never fails, a missing or broken file gives the defaults
+    fn load(path: &Path) -> Self {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => {
+                info!("no settings at {:?}, using defaults", path);
+                let defaults = Self::default();
+                defaults.write(path);
+                return defaults;
+            }
+        };
+        let mut file: SettingsFile = match ron::from_str(&text) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("malformed settings at {:?}, using defaults : {}", path, e);
+                return Self::default();
+            }
+        };
+        //actions added since the file was written get their default bindings
+        for (action, bindings) in InputSettings::default().bindings {
+            file.input.bindings.entry(action).or_insert(bindings);
+        }
+        for fixed in file.gameplay.validate() {
+            warn!("settings : {}", fixed);
+        }
+        for (binding, actions) in file.input.conflicts() {
+            warn!("settings : {:?} is bound to {:?}", binding, actions);
+        }
+        file
+    }
+
+    fn write(&self, path: &Path) {
+        let text = match ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()) {
+            Ok(text) => text,
+            Err(e) => {
+                error!("could not serialize settings : {}", e);
+                return;
+            }
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        if let Err(e) = fs::write(path, text) {
+            error!("could not write settings to {:?} : {}", path, e);
+        }
+    }
+
+    fn into_settings(self) -> (GameplaySettings, InputSettings) {
+        (self.gameplay, self.input)
+    }
+}
+
+/// Write settings back whenever something modified them at runtime
+fn save_changed_settings(path: Res<SettingsPath>,
+                         gameplay: Res<GameplaySettings>,
+                         input: Res<InputSettings>) {
+    if gameplay.is_added() && input.is_added() {
+        return;
+    }
+    if !gameplay.is_changed() && !input.is_changed() {
+        return;
+    }
+    if let Some(path) = path.0.as_deref() {
+        SettingsFile {
+            gameplay: gameplay.clone(),
+            input: input.clone(),
+        }.write(path);
     }
 }
@@ -1,29 +1,243 @@
+use std::time::Duration;
+
 use bevy::app::App;
 use bevy::prelude::*;
 
+use super::actions::{Action, ActionState, ActionSystem};
+use super::console::ConsoleCommands;
+use super::scheduler::{register_jobs_command, Scheduler, SchedulerSystem, update_scheduler};
+
+/// Number of simulation frames since startup, orders and snapshots are stamped with it
+#[derive(Resource, Default, Copy, Clone, Debug)]
+pub struct SimTick(pub u64);
+
+/// Speeds offered to the player, in sim seconds per real second
+pub const SIM_SPEEDS: [f64; 3] = [1.0, 10.0, 100.0];
+/// Longer frames are cut to this before scaling, so a hitch doesn't teleport ships
+const MAX_FRAME_DELTA: Duration = Duration::from_millis(250);
+/// Year of the game date when the sim clock is at zero
+pub const EPOCH_YEAR: u32 = 2400;
+const DAYS_PER_YEAR: u64 = 365;
+
+/// Time as seen by the simulation, sim systems read this instead of `Time`.
+/// A fixed step ignores pause and scale, so recorded runs stay reproducible.
 #[derive(Resource)]
-pub struct OneSecondTimer(pub Timer);
+pub struct SimClock {
+    delta: Duration,
+    elapsed: Duration,
+    /// Every tick lasts exactly this long, so runs don't depend on frame times
+    pub fixed_step: Option<Duration>,
+    /// Sim seconds per real second
+    pub time_scale: f64,
+    pub paused: bool,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self {
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            fixed_step: None,
+            time_scale: 1.0,
+            paused: false,
+        }
+    }
+}
+
+impl SimClock {
+    pub fn fixed(step: Duration) -> Self {
+        Self { fixed_step: Some(step), ..default() }
+    }
+
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds_f64(&self) -> f64 {
+        self.delta.as_secs_f64()
+    }
+
+    /// Simulated time since the epoch, carried over by saves
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Only for loading, sim systems never jump in time
+    pub fn set_elapsed(&mut self, elapsed: Duration) {
+        self.elapsed = elapsed;
+    }
 
+    pub fn date(&self) -> GameDate {
+        GameDate::from_elapsed(self.elapsed)
+    }
+
+    /// Next speed of [`SIM_SPEEDS`] up or down from the current one, unpauses
+    pub fn step_speed(&mut self, faster: bool) {
+        let current = SIM_SPEEDS.iter().position(|s| *s >= self.time_scale).unwrap_or(SIM_SPEEDS.len() - 1);
+        let next = if faster { (current + 1).min(SIM_SPEEDS.len() - 1) } else { current.saturating_sub(1) };
+        self.time_scale = SIM_SPEEDS[next];
+        self.paused = false;
+    }
+}
+
+/// Calendar of the game, 365 days a year and no months
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct GameDate {
+    pub year: u32,
+    /// From 1
+    pub day: u16,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl GameDate {
+    pub fn from_elapsed(elapsed: Duration) -> Self {
+        let secs = elapsed.as_secs();
+        let days = secs / 86_400;
+        Self {
+            year: EPOCH_YEAR + (days / DAYS_PER_YEAR) as u32,
+            day: (days % DAYS_PER_YEAR) as u16 + 1,
+            hour: (secs / 3600 % 24) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+
+    /// Sim time at which this date starts
+    pub fn to_elapsed(&self) -> Duration {
+        let years = self.year.saturating_sub(EPOCH_YEAR) as u64;
+        let days = years * DAYS_PER_YEAR + self.day.saturating_sub(1) as u64;
+        Duration::from_secs(days * 86_400 + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64)
+    }
+}
+
+impl std::fmt::Display for GameDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{:03} {:02}:{:02}:{:02}", self.year, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+/// Events of type `E` waiting for a sim time, sent once the clock gets there.
+/// Register with [`ScheduledEventPlugin`], the schedule is not saved.
 #[derive(Resource)]
-pub struct FiveSecondTimer(pub Timer);
+pub struct SimSchedule<E> {
+    /// Sorted by time, same times keep their scheduling order
+    pending: Vec<(Duration, E)>,
+}
+
+impl<E> Default for SimSchedule<E> {
+    fn default() -> Self {
+        Self { pending: Vec::new() }
+    }
+}
+
+impl<E> SimSchedule<E> {
+    pub fn at(&mut self, at: Duration, event: E) {
+        let i = self.pending.partition_point(|(t, _)| *t <= at);
+        self.pending.insert(i, (at, event));
+    }
+
+    pub fn after(&mut self, clock: &SimClock, delay: Duration, event: E) {
+        self.at(clock.elapsed() + delay, event);
+    }
+
+    /// Pending events, soonest first
+    pub fn iter(&self) -> impl Iterator<Item=&E> {
+        self.pending.iter().map(|(_, e)| e)
+    }
+
+    pub fn next_time(&self) -> Option<Duration> {
+        self.pending.first().map(|(t, _)| *t)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Sends the events of a [`SimSchedule<E>`] when their time comes, before `CoreStage::Update`
+pub struct ScheduledEventPlugin<E>(std::marker::PhantomData<E>);
+
+impl<E> Default for ScheduledEventPlugin<E> {
+    fn default() -> Self {
+        Self(std::marker::PhantomData)
+    }
+}
+
+impl<E: Send + Sync + 'static> Plugin for ScheduledEventPlugin<E> {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<SimSchedule<E>>()
+            .add_event::<E>()
+            .add_system_to_stage(CoreStage::PreUpdate, send_scheduled_events::<E>);
+    }
+}
+
+fn send_scheduled_events<E: Send + Sync + 'static>(clock: Res<SimClock>,
+                                                   mut schedule: ResMut<SimSchedule<E>>,
+                                                   mut events: EventWriter<E>) {
+    let due = schedule.pending.partition_point(|(t, _)| *t <= clock.elapsed());
+    events.send_batch(schedule.pending.drain(..due).map(|(_, e)| e));
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SimClockSystem {
+    Advance,
+}
 
 pub struct TimerPlugin;
 
 
-fn tick_timers(time: Res<Time>,
-               mut timer1: ResMut<OneSecondTimer>,
-               mut timer5: ResMut<FiveSecondTimer>) {
-    timer1.0.tick(time.delta());
-    timer5.0.tick(time.delta());
+fn advance_sim_clock(time: Res<Time>,
+                     mut tick: ResMut<SimTick>,
+                     mut clock: ResMut<SimClock>) {
+    tick.0 += 1;
+    clock.delta = match clock.fixed_step {
+        Some(step) => step,
+        None if clock.paused => Duration::ZERO,
+        None => time.delta().min(MAX_FRAME_DELTA).mul_f64(clock.time_scale),
+    };
+    let delta = clock.delta;
+    clock.elapsed += delta;
+}
+
+fn sim_speed_input(actions: Option<Res<ActionState>>,
+                   mut clock: ResMut<SimClock>) {
+    let Some(actions) = actions else { return; };
+    if actions.just_pressed(Action::PauseSim) {
+        clock.paused = !clock.paused;
+    }
+    if actions.just_pressed(Action::SimFaster) {
+        clock.step_speed(true);
+    }
+    if actions.just_pressed(Action::SimSlower) {
+        clock.step_speed(false);
+    }
 }
 
 
 impl Plugin for TimerPlugin {
     fn build(&self, app: &mut App) {
-        // add things to your app here
+        //all three can be set up front, e.g. to resume a replay at its tick,
+        //plugins built earlier may already have added jobs
         app
-            .insert_resource(OneSecondTimer(Timer::from_seconds(1.0, TimerMode::Repeating)))
-            .insert_resource(FiveSecondTimer(Timer::from_seconds(5.0, TimerMode::Repeating)))
-            .add_system(tick_timers);
+            .init_resource::<SimTick>()
+            .init_resource::<SimClock>()
+            .init_resource::<Scheduler>()
+            //`Time` is updated by an exclusive system, those run before the regular ones of the stage
+            .add_system_to_stage(CoreStage::First, advance_sim_clock
+                .label(SimClockSystem::Advance))
+            .add_system_to_stage(CoreStage::First, update_scheduler
+                .label(SchedulerSystem::Update)
+                .after(SimClockSystem::Advance))
+            .add_system_to_stage(CoreStage::PreUpdate, sim_speed_input.after(ActionSystem::Update));
+        if let Some(mut commands) = app.world.get_resource_mut::<ConsoleCommands>() {
+            register_jobs_command(&mut commands);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,104 @@
+//! Distance units, kept apart by type so they can't be mixed up.
+//!
+//! Physics (velocities, forces, ranges) is in [`Metres`], [`SimPosition`](crate::space::galaxy::SimPosition)
+//! is in [`SimUnits`] to keep f64 coordinates small, and the projection works in screen [`Pixels`].
+//! Metres and sim units only convert through [`GalaxyScale`], vectors as well : a velocity in m/s
+//! gives a [`MetresVec`] over some time, which becomes the [`SimVec`] added to a position.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const METRES_PER_AU: f64 = 149_597_870_700.0;
+
+/// Sim units per metre used by the game
+pub const SIM_UNITS_PER_METRE: f64 = 0.000001;
+
+macro_rules! distance_unit {
+    ($name:ident) => {
+        #[derive(Debug, Default, Copy, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+        pub struct $name(pub f64);
+
+        impl Add for $name {
+            type Output = Self;
+            fn add(self, rhs: Self) -> Self { Self(self.0 + rhs.0) }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self { Self(self.0 - rhs.0) }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+            fn neg(self) -> Self { Self(-self.0) }
+        }
+
+        impl Mul<f64> for $name {
+            type Output = Self;
+            fn mul(self, rhs: f64) -> Self { Self(self.0 * rhs) }
+        }
+
+        impl Div<f64> for $name {
+            type Output = Self;
+            fn div(self, rhs: f64) -> Self { Self(self.0 / rhs) }
+        }
+
+        /// Ratio between two distances
+        impl Div for $name {
+            type Output = f64;
+            fn div(self, rhs: Self) -> f64 { self.0 / rhs.0 }
+        }
+    };
+}
+
+distance_unit!(Metres);
+distance_unit!(Au);
+distance_unit!(SimUnits);
+distance_unit!(Pixels);
+
+impl From<Au> for Metres {
+    fn from(au: Au) -> Self {
+        Metres(au.0 * METRES_PER_AU)
+    }
+}
+
+impl From<Metres> for Au {
+    fn from(m: Metres) -> Self {
+        Au(m.0 / METRES_PER_AU)
+    }
+}
+
+/// How many sim units a metre is worth
+#[derive(Resource, Copy, Clone)]
+pub struct GalaxyScale(f64);
+
+impl Default for GalaxyScale {
+    fn default() -> Self {
+        Self(SIM_UNITS_PER_METRE)
+    }
+}
+
+impl GalaxyScale {
+    pub fn to_sim(&self, m: Metres) -> SimUnits {
+        SimUnits(m.0 * self.0)
+    }
+
+    pub fn to_metres(&self, s: SimUnits) -> Metres {
+        Metres(s.0 / self.0)
+    }
+
+    pub fn to_sim_vec(&self, m: MetresVec) -> SimVec {
+        SimVec(m.0 * self.0)
+    }
+}
+
+/// Displacement in metres, e.g. what a [`Velocity`](crate::base::velocity::Velocity) covers over some time
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct MetresVec(pub DVec3);
+
+/// Displacement in sim units, what moves a [`SimPosition`](crate::space::galaxy::SimPosition)
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SimVec(pub DVec3);
@@ -1,27 +1,49 @@
-use bevy::math::DVec2;
+use bevy::math::DVec3;
 use bevy::prelude::*;
 
+use crate::base::timer::SimClock;
+use crate::base::units::MetresVec;
 use crate::space::galaxy::{GalaxyScale, SimPosition};
 
 ///Velocity of an entity, in m/s
 #[derive(Component, Default, Deref, DerefMut)]
-pub struct Velocity(pub DVec2);
+pub struct Velocity(pub DVec3);
+
+/// Keeps an entity on the `z = value` plane, in sim units
+#[derive(Component, Copy, Clone)]
+pub struct PlaneLock(pub f64);
+
+impl Velocity {
+    /// Distance covered in `seconds`
+    pub fn over(&self, seconds: f64) -> MetresVec {
+        MetresVec(self.0 * seconds)
+    }
+}
 
 //TODO implement some real space drag / max speed for ships
-fn apply_velocity(time: Res<Time>,
+fn apply_velocity(clock: Res<SimClock>,
                   scale: Res<GalaxyScale>,
-                  mut query: Query<(&mut SimPosition, &Velocity)>) {
-    for (mut sPos, velocity) in &mut query {
+                  mut query: Query<(&mut SimPosition, &mut Velocity, Option<&PlaneLock>)>) {
+    for (mut s_pos, mut velocity, lock) in &mut query {
         //println!("vel : {:?}",velocity.0);
-        sPos.0.x += velocity.x * time.delta_seconds_f64() * scale.0;
-        sPos.0.y += velocity.y * time.delta_seconds_f64() * scale.0;
+        if let Some(lock) = lock {
+            velocity.0.z = 0.0;
+            s_pos.0.z = lock.0;
+        }
+        *s_pos += scale.to_sim_vec(velocity.over(clock.delta_seconds_f64()));
     }
 }
 
 
+/// Ships move once per frame, systems steering or reading them order themselves around it
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum VelocitySystem {
+    Apply,
+}
+
 pub struct VelocityPlugin;
 impl Plugin for VelocityPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(apply_velocity);
+        app.add_system(apply_velocity.label(VelocitySystem::Apply));
     }
 }
\ No newline at end of file
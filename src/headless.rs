@@ -0,0 +1,112 @@
+//! Simulation without window, rendering or picking, for soak runs on build servers.
+
+use std::time::{Duration, Instant};
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+use crate::base::timer::SimClock;
+use crate::base::velocity::Velocity;
+use crate::space::activity::{OreMinedEvent, PilotKilledEvent};
+use crate::space::pilot::Pilot;
+use crate::space::progression::PilotLevelUp;
+use crate::space::security::{AggressionEvent, CriminalFlag, Police};
+use crate::space::skills::SkillTrainedEvent;
+
+/// When a headless run stops, it runs until killed without one
+#[derive(Debug, Copy, Clone)]
+pub enum RunLimit {
+    Ticks(u64),
+    /// Simulated time
+    Duration(Duration),
+}
+
+pub struct HeadlessPlugin {
+    pub limit: Option<RunLimit>,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(HeadlessRun {
+                limit: self.limit,
+                ticks: 0,
+                simulated: Duration::ZERO,
+                started: Instant::now(),
+            })
+            .insert_resource(RunStats::default())
+            .add_system(count_events)
+            //before `Last`, where the recorder writes its file on exit
+            .add_system_to_stage(CoreStage::PostUpdate, stop_at_limit);
+    }
+}
+
+#[derive(Resource)]
+pub struct HeadlessRun {
+    pub limit: Option<RunLimit>,
+    pub ticks: u64,
+    /// Sim time of this run, a loaded universe starts later than the epoch
+    pub simulated: Duration,
+    started: Instant,
+}
+
+/// Events seen since the start of the run
+#[derive(Resource, Default)]
+pub struct RunStats {
+    pub level_ups: u64,
+    pub skills_trained: u64,
+    pub aggressions: u64,
+    pub kills: u64,
+    /// m3
+    pub ore_mined: f64,
+}
+
+fn count_events(mut stats: ResMut<RunStats>,
+                mut level_ups: EventReader<PilotLevelUp>,
+                mut trained: EventReader<SkillTrainedEvent>,
+                mut aggressions: EventReader<AggressionEvent>,
+                mut kills: EventReader<PilotKilledEvent>,
+                mut mined: EventReader<OreMinedEvent>) {
+    stats.level_ups += level_ups.iter().count() as u64;
+    stats.skills_trained += trained.iter().count() as u64;
+    stats.aggressions += aggressions.iter().count() as u64;
+    stats.kills += kills.iter().count() as u64;
+    stats.ore_mined += mined.iter().map(|ev| ev.volume).sum::<f64>();
+}
+
+fn stop_at_limit(clock: Res<SimClock>,
+                 mut run: ResMut<HeadlessRun>,
+                 stats: Res<RunStats>,
+                 pilots: Query<(&Pilot, Option<&Velocity>)>,
+                 police: Query<(), With<Police>>,
+                 criminals: Query<(), With<CriminalFlag>>,
+                 mut exit: EventWriter<AppExit>) {
+    run.ticks += 1;
+    run.simulated += clock.delta();
+    let done = match run.limit {
+        Some(RunLimit::Ticks(ticks)) => run.ticks >= ticks,
+        Some(RunLimit::Duration(duration)) => run.simulated >= duration,
+        None => false,
+    };
+    if !done {
+        return;
+    }
+
+    let wall = run.started.elapsed();
+    let in_space = pilots.iter().filter(|(_, v)| v.is_some()).count();
+    let levels: Vec<u8> = pilots.iter().map(|(p, _)| p.level).collect();
+    let average_level = levels.iter().map(|l| *l as f64).sum::<f64>() / levels.len().max(1) as f64;
+
+    info!("headless run finished");
+    info!("  ticks          : {}", run.ticks);
+    info!("  simulated time : {:.1}s, now {}", run.simulated.as_secs_f64(), clock.date());
+    info!("  wall time      : {:.1}s ({:.3} ms/tick)", wall.as_secs_f64(), wall.as_secs_f64() * 1000.0 / run.ticks as f64);
+    info!("  pilots         : {} ({} in space, {} docked)", levels.len(), in_space, levels.len() - in_space);
+    info!("  levels         : avg {:.2}, max {}", average_level, levels.iter().max().copied().unwrap_or(0));
+    info!("  level ups      : {}", stats.level_ups);
+    info!("  skills trained : {}", stats.skills_trained);
+    info!("  aggressions    : {}, kills {}", stats.aggressions, stats.kills);
+    info!("  ore mined      : {:.0} m3", stats.ore_mined);
+    info!("  police         : {} ships, {} flagged pilots", police.iter().count(), criminals.iter().count());
+    exit.send(AppExit);
+}
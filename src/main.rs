@@ -1,115 +1,272 @@
-use std::default;
+//Bevy systems take their queries and resources as arguments
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
 
-use bevy::app::App;
-use bevy::input::mouse::MouseMotion;
+use std::path::{Path, PathBuf};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use bevy::app::{App, ScheduleRunnerSettings};
 use bevy::math::DVec3;
 use bevy::prelude::*;
-use bevy::sprite::MaterialMesh2dBundle;
+#[cfg(feature = "editor")]
 use bevy_editor_pls::prelude::*;
 use bevy_mod_picking::*;
-use rand::{Rng, thread_rng};
 
-use space::galaxy::{GalaxyCoordinate, SolarSystem, SystemMap};
-use space::ship::pilot::*;
+use space::galaxy::{GalaxyCoordinate, SolarSystem, spawn_solar_system, SystemMap};
+use space::pilot::*;
 use space::SpaceGamePlugins;
 
 use crate::base::*;
+use crate::base::camera::CameraControllerPlugin;
+use crate::base::console::{ConsoleOverlayPlugin, ConsoleStdinPlugin};
+use crate::base::hud::HudPlugin;
+use crate::base::rng::SimRng;
 use crate::base::timer::*;
-use crate::DestoType::TEntity;
-use crate::space::galaxy::SimPosition;
+use crate::headless::{HeadlessPlugin, RunLimit};
+use crate::net::client::ClientPlugin;
+use crate::net::DEFAULT_PORT;
+use crate::net::server::ServerPlugin;
+use crate::space::faction::{FactionDef, FactionRegistry, Standings};
+use crate::space::galaxy::{GalaxyScale, SimPosition};
+use crate::space::mining::{field_position, ore_field};
+use crate::space::{GalaxyPlugin, GalaxyViewPlugin};
+use crate::space::context_menu::ContextMenuPlugin;
+use crate::space::overview::OverviewPlugin;
+use crate::space::presentation::PresentationPlugin;
+use crate::space::selection::SelectionPlugin;
+use crate::space::replay::{read_replay, RecorderPlugin, REPLAY_STEP, ReplayPlugin};
+use crate::space::save::{dry_run_migration, PendingLoad};
+use crate::space::security::SecurityStatus;
 use crate::space::ship::*;
-use crate::space::station::{AnchorableBundle, spawn_station_at};
+use crate::space::station::spawn_station_at;
 
 pub mod base;
+pub mod headless;
+pub mod net;
 pub mod space;
 
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .add_plugin(EditorPlugin)
-        .add_plugins(DefaultPickingPlugins)
-        .add_plugin(DebugEventsPickingPlugin)
-        .add_plugins(BaseLogicPlugins)
-        .add_plugins(SpaceGamePlugins)
-        .add_plugin(TimerPlugin)
-        .add_startup_system(setup)
-        //.add_system(frame_update)
-        //.add_system(follow_mouse)
-        .run();
-}
+    //`--load <file>` restores a saved universe instead of generating a new one
+    let args: Vec<String> = std::env::args().collect();
+    let load = args.iter()
+        .position(|a| a == "--load")
+        .and_then(|i| args.get(i + 1))
+        .map(PathBuf::from);
+
+    //`--migrate-dry-run <file>` reports what loading an old save would change, then exits
+    if let Some(path) = args.iter().position(|a| a == "--migrate-dry-run").and_then(|i| args.get(i + 1)) {
+        match dry_run_migration(Path::new(path)) {
+            Ok(report) => {
+                println!("{} : version {} -> {}", path, report.from, report.to);
+                for change in report.changes.iter() {
+                    println!("  {}", change);
+                }
+                if report.changes.is_empty() {
+                    println!("  already up to date");
+                }
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("{} : cannot migrate, {:?}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
 
-#[derive(Component)]
-struct TestTag;
+    //`--connect <addr> [--pilot <uid>]` shows a server's universe instead of simulating one
+    if let Some(addr) = arg_value::<String>(&args, "--connect") {
+        let addr = parse_addr(&addr);
+        let pilot_uid = arg_value(&args, "--pilot").unwrap_or(1);
+        App::new()
+            .add_plugins(DefaultPlugins)
+            .add_plugins(DefaultPickingPlugins)
+            .add_plugins(BaseLogicPlugins)
+            .add_plugin(GalaxyPlugin)
+            .add_plugin(GalaxyViewPlugin)
+            .add_plugin(PresentationPlugin)
+            .add_plugin(SelectionPlugin)
+            .add_plugin(ContextMenuPlugin)
+            .add_plugin(OverviewPlugin)
+            .add_plugin(TimerPlugin)
+            .add_plugin(ClientPlugin { addr, pilot_uid })
+            .run();
+        return;
+    }
 
+    //`--replay <file>` runs a recording again headless and reports the first tick where it diverges
+    let replay = arg_value::<PathBuf>(&args, "--replay").map(|path| match read_replay(&path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("{:?} : cannot read replay, {:?}", path, e);
+            std::process::exit(1);
+        }
+    });
 
-fn follow_mouse(mut query: Query<&mut Transform>,
-                mut motion_evr: EventReader<MouseMotion>,
-                mouse_button_input: Res<Input<MouseButton>>) {
-    if mouse_button_input.pressed(MouseButton::Left) {
-        for mut transf in &mut query {
-            for ev in motion_evr.iter() {
-                transf.translation += Vec3::new(ev.delta.x, -ev.delta.y, 0.0);
-            }
+    let mut app = App::new();
+    let mut tick_rate = 60.0;
+    //`--headless [--ticks <n> | --duration <seconds>] [--tick-rate <hz>] [--step <seconds>]` runs the simulation alone, without window
+    if replay.is_some() || args.iter().any(|a| a == "--headless") {
+        let limit = arg_value(&args, "--ticks").map(RunLimit::Ticks)
+            .or_else(|| arg_value(&args, "--duration").map(|s: f64| RunLimit::Duration(Duration::from_secs_f64(s))));
+        //0 runs as fast as possible
+        tick_rate = arg_value(&args, "--tick-rate").unwrap_or(tick_rate);
+        let wait = if tick_rate > 0.0 { Duration::from_secs_f64(1.0 / tick_rate) } else { Duration::ZERO };
+        //every tick simulates a fixed step whatever the wall time, so fast ticks fast-forward
+        let step = arg_value(&args, "--step").filter(|s: &f64| *s > 0.0).map(Duration::from_secs_f64)
+            .unwrap_or(if tick_rate > 0.0 { wait } else { REPLAY_STEP });
+        app
+            .insert_resource(ScheduleRunnerSettings::run_loop(wait))
+            .insert_resource(SimClock::fixed(step))
+            .add_plugins(MinimalPlugins)
+            .add_plugin(bevy::log::LogPlugin::default())
+            .add_plugins(BaseLogicPlugins.build()
+                .disable::<CameraControllerPlugin>()
+                .disable::<ConsoleOverlayPlugin>()
+                .disable::<HudPlugin>())
+            .add_plugin(ConsoleStdinPlugin)
+            .add_plugins(SpaceGamePlugins.build()
+                .disable::<GalaxyViewPlugin>()
+                .disable::<PresentationPlugin>()
+                .disable::<OverviewPlugin>()
+                .disable::<SelectionPlugin>()
+                .disable::<ContextMenuPlugin>())
+            .add_plugin(TimerPlugin)
+            .add_plugin(HeadlessPlugin { limit });
+    } else {
+        app.add_plugins(DefaultPlugins);
+        #[cfg(feature = "editor")]
+        app.add_plugin(EditorPlugin);
+        app
+            .add_plugins(DefaultPickingPlugins)
+            .add_plugin(DebugEventsPickingPlugin)
+            .add_plugins(BaseLogicPlugins)
+            .add_plugins(SpaceGamePlugins)
+            .add_plugin(TimerPlugin);
+            //.add_system(frame_update)
+    }
+
+    //`--server <addr>` lets clients connect, works with and without window
+    if let Some(addr) = arg_value::<String>(&args, "--server") {
+        app.add_plugin(ServerPlugin { addr: parse_addr(&addr), tick_rate });
+    }
+
+    //`--seed <n>` fixes every random draw of the simulation
+    if let Some(seed) = arg_value(&args, "--seed") {
+        app.insert_resource(SimRng::seeded(seed));
+    }
+
+    //`--record <file>` writes every order and periodic state hashes for `--replay`
+    if let Some(path) = arg_value::<PathBuf>(&args, "--record") {
+        app
+            .insert_resource(SimClock::fixed(REPLAY_STEP))
+            .add_plugin(RecorderPlugin { path });
+    }
+
+    let diverged = Arc::new(AtomicBool::new(false));
+    match (replay, load) {
+        (Some(replay), _) => {
+            app
+                .insert_resource(SimRng::seeded(replay.seed))
+                .insert_resource(SimClock::fixed(replay.step))
+                .insert_resource(SimTick(replay.start_tick))
+                .add_plugin(ReplayPlugin { orders: replay.orders, actions: replay.actions, hashes: replay.hashes, diverged: diverged.clone() })
+                .insert_resource(PendingLoad::Save(Box::new(replay.initial)));
         }
+        (None, Some(path)) => { app.insert_resource(PendingLoad::File(path)); }
+        (None, None) => { app.add_startup_system(setup); }
     }
+    app.run();
+    if diverged.load(Ordering::Relaxed) {
+        std::process::exit(1);
+    }
+}
+
+/// Value following `name` on the command line, `None` if missing or unparsable
+fn arg_value<T: std::str::FromStr>(args: &[String], name: &str) -> Option<T> {
+    args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).and_then(|v| v.parse().ok())
 }
 
+/// `host:port`, or just a host on the default port, exits if it doesn't resolve
+fn parse_addr(addr: &str) -> SocketAddr {
+    let parsed = addr.to_socket_addrs().ok().and_then(|mut a| a.next())
+        .or_else(|| (addr, DEFAULT_PORT).to_socket_addrs().ok().and_then(|mut a| a.next()));
+    match parsed {
+        Some(addr) => addr,
+        None => {
+            eprintln!("{} : invalid address", addr);
+            std::process::exit(1);
+        }
+    }
+}
 
 fn setup(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
     mut cluster: ResMut<SystemMap>,
+    mut factions: ResMut<FactionRegistry>,
+    mut standings: ResMut<Standings>,
+    mut names: ResMut<PilotNameGenerator>,
+    scale: Res<GalaxyScale>,
 ) {
-    let mut rng = thread_rng();
+    let faction_defs = [
+        ("Solar Concord", Color::GOLD, NameLexicon::new(
+            &["au", "re", "li", "ca", "vi", "to"],
+            &["us", "a", "ia", "or"],
+            &["val", "er", "ian", "cor", "ne"],
+        )),
+        ("Free Traders", Color::AQUAMARINE, NameLexicon::default()),
+        ("Red Syndicate", Color::CRIMSON, NameLexicon::new(
+            &["kr", "zar", "vo", "dra", "ish", "gul"],
+            &["ek", "ov", "ax", "uk"],
+            &["rak", "zo", "vek", "tor", "gash"],
+        )),
+    ];
+    let securities = [1.0, 0.4, -0.3];
+    let mut faction_ids = Vec::new();
     for i in 0..3 {
-        let id = commands.spawn(
-            (
-                SolarSystem {
-                    anomalies: Vec::new(),
-                    gates: Vec::new(),
-                },
-                UndockLoc,
-                SimPosition(DVec3 {
-                    x: -500.0 + (500.0 * i as f64),
-                    y: 0.0,
-                    z: 0.0,
-                }, ),
-                MaterialMesh2dBundle {
-                    mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
-                    material: materials.add(ColorMaterial::from(Color::RED)),
-                    transform: Transform {
-                        translation: Vec3 {
-                            x: -500.0 + (500.0 * i as f32),
-                            y: 0.0,
-                            z: 0.0,
-                        },
-                        scale: Vec3 { x: 64.0, y: 64.0, z: 1.0 },
-                        ..default()
-                    },
-                    visibility: Visibility { is_visible: true },
-                    ..default()
-                },
-                PickableBundle::default(),
-            )).remove::<Selection>().id();
+        let id = spawn_solar_system(
+            &mut commands,
+            DVec3 {
+                x: -500.0 + (500.0 * i as f64),
+                y: 0.0,
+                z: 0.0,
+            },
+            SecurityStatus(securities[i]),
+        );
 
         cluster.0.push(id);
 
+        let (name, color, lexicon) = &faction_defs[i];
+        let faction = factions.register(FactionDef {
+            name: name.to_string(),
+            home_systems: vec![id],
+            color: *color,
+            lexicon: lexicon.clone(),
+        });
+        names.use_lexicons(&factions);
+        faction_ids.push(faction);
+
         let station = commands.spawn((
-            spawn_station_at(SimPosition(DVec3::ZERO), id),
+            spawn_station_at(SimPosition(DVec3::ZERO), id, faction),
             UndockLoc,
         )).id();
+        let field = commands.spawn(ore_field(id, field_position(DVec3::ZERO, &scale))).id();
+        commands.entity(id).insert(SolarSystem { anomalies: vec![field], gates: Vec::new() });
 
         for _ in 0..10 {
             commands.spawn((
-                spawn_new_pilot(),
+                spawn_new_pilot(faction, &mut names),
                 UndockingFrom(station),
             ));
         }
     }
 
-
+    //the syndicate and the concord are at war
+    standings.set_faction(faction_ids[0], faction_ids[2], -10.0);
+    standings.set_faction(faction_ids[2], faction_ids[0], -10.0);
+    standings.set_faction(faction_ids[0], faction_ids[1], 2.0);
 
     /* 
     // Cube
@@ -156,28 +313,3 @@ fn setup(
     */
 }
 
-
-fn test_move(
-    mut query_ships: Query<&mut Destination, Without<TestTag>>,
-    query_targets: Query<&SimPosition, With<TestTag>>) {
-    let mut t: Option<&SimPosition> = None;
-    let mut min_dist: f64 = f64::MAX;
-    for tr in query_targets.iter() {
-        let dist = tr.0.length_squared();
-        if dist < min_dist {
-            min_dist = dist;
-            t = Some(tr)
-        }
-    }
-
-    //println!("{:?}",t);
-
-    for mut dest in &mut query_ships {
-        match t {
-            Some(tr) => {
-                dest.0 = TEntity(*tr);
-            }
-            None => {}
-        }
-    }
-}
@@ -0,0 +1,18 @@
+//! Multiplayer over TCP : the server runs the whole simulation and replicates to each client
+//! the entities of the system its pilot is in, clients only show that state and send orders.
+//!
+//! Try it on localhost with `--headless --server 127.0.0.1:7777` and `--connect 127.0.0.1:7777 --pilot 1`.
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+pub mod client;
+pub mod protocol;
+pub mod server;
+
+pub const DEFAULT_PORT: u16 = 7777;
+
+/// Startup system of an app whose connection failed, it stops without running a frame of it
+fn exit_app(mut exit: EventWriter<AppExit>) {
+    exit.send(AppExit);
+}
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::{SocketAddr, TcpStream};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::space::galaxy::{Rendered, SimPosition, ViewState};
+use crate::space::orders::{OrderEvent, ShipOrder};
+use crate::space::faction::Standings;
+use crate::space::pilot::{EName, Faction};
+use crate::space::security::Police;
+use crate::space::ship::HullClass;
+use crate::space::station::Station;
+
+use super::exit_app;
+use super::protocol::{ClientMessage, Connection, NetKind, NetOrder, patch, ServerMessage, WorldState};
+
+/// How far behind the last snapshot entities are shown, in ticks, so there is always a next sample
+const INTERPOLATION_DELAY: f64 = 6.0;
+/// Past this drift the clock jumps instead of catching up
+const MAX_CLOCK_DRIFT: f64 = 30.0;
+const RECEIVED_HISTORY: usize = 32;
+const SAMPLES_KEPT: usize = 16;
+
+/// Shows the server's simulation, local orders are forwarded instead of applied
+pub struct ClientPlugin {
+    pub addr: SocketAddr,
+    pub pilot_uid: u64,
+}
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        let mut conn = match TcpStream::connect(self.addr).and_then(Connection::new) {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("cannot connect to {} : {}", self.addr, e);
+                app.add_startup_system(exit_app);
+                return;
+            }
+        };
+        conn.send(&ClientMessage::Hello { pilot_uid: self.pilot_uid });
+        info!("connected to {}", self.addr);
+
+        app
+            .insert_resource(NetClient {
+                conn,
+                pilot: None,
+                tick_rate: 60.0,
+                clock: None,
+                received: VecDeque::new(),
+                entities: HashMap::new(),
+            })
+            .add_event::<OrderEvent>()
+            //standings aren't replicated, everyone looks neutral to the menus and the overview
+            .init_resource::<Standings>()
+            .add_system_to_stage(CoreStage::PreUpdate, receive_server_messages)
+            .add_system(interpolate_positions)
+            .add_system(forward_orders)
+            .add_system_to_stage(CoreStage::Last, flush_client);
+    }
+}
+
+#[derive(Resource)]
+pub struct NetClient {
+    conn: Connection,
+    /// Server bits of our pilot, `None` until welcomed or if the pilot doesn't exist
+    pub pilot: Option<u64>,
+    tick_rate: f64,
+    /// Server tick being shown, fractional
+    clock: Option<f64>,
+    received: VecDeque<(u64, WorldState)>,
+    entities: HashMap<u64, Entity>,
+}
+
+impl NetClient {
+    fn latest_tick(&self) -> Option<u64> {
+        self.received.back().map(|(t, _)| *t)
+    }
+}
+
+/// Local copy of a server entity
+#[derive(Component)]
+pub struct Replicated {
+    pub server_id: u64,
+}
+
+/// Server positions by tick, oldest first
+#[derive(Component, Default)]
+pub struct NetSamples(VecDeque<(u64, DVec3)>);
+
+fn receive_server_messages(mut commands: Commands,
+                           mut client: ResMut<NetClient>,
+                           mut state: ResMut<State<ViewState>>,
+                           mut existing: Query<(&mut NetSamples, Option<&mut HullClass>)>) {
+    let was_open = !client.conn.closed;
+    let messages = client.conn.receive::<ServerMessage>();
+    if was_open && client.conn.closed {
+        warn!("lost connection to the server");
+    }
+
+    for message in messages {
+        match message {
+            ServerMessage::Welcome { pilot, tick, tick_rate } => {
+                if pilot.is_none() {
+                    warn!("the server doesn't know our pilot, spectating");
+                }
+                client.pilot = pilot;
+                client.tick_rate = tick_rate;
+                client.clock = Some(tick as f64 - INTERPOLATION_DELAY);
+                state.set(ViewState::SYSTEM).ok();
+            }
+            ServerMessage::Snapshot(snapshot) => {
+                let empty = WorldState::new();
+                let baseline = match snapshot.baseline {
+                    Some(tick) => match client.received.iter().find(|(t, _)| *t == tick) {
+                        Some((_, state)) => state,
+                        //should not happen, the server only uses acked snapshots
+                        None => continue,
+                    },
+                    None => &empty,
+                };
+                let Some(world) = patch(baseline, &snapshot.entities, &snapshot.removed) else {
+                    warn!("snapshot {} is incomplete, dropped", snapshot.tick);
+                    continue;
+                };
+
+                //despawn what left our system, spawn or update the rest
+                let NetClient { entities, .. } = &mut *client;
+                entities.retain(|id, entity| {
+                    let keep = world.contains_key(id);
+                    if !keep {
+                        commands.entity(*entity).despawn_recursive();
+                    }
+                    keep
+                });
+                for (id, net) in world.iter() {
+                    let position = DVec3::from_array(net.position);
+                    if let Some((mut samples, hull)) = entities.get(id).and_then(|e| existing.get_mut(*e).ok()) {
+                        samples.0.push_back((snapshot.tick, position));
+                        while samples.0.len() > SAMPLES_KEPT {
+                            samples.0.pop_front();
+                        }
+                        if let (NetKind::Ship { hull: new_hull, .. }, Some(mut hull)) = (&net.kind, hull) {
+                            if *hull != *new_hull {
+                                *hull = *new_hull;
+                            }
+                        }
+                        continue;
+                    }
+
+                    let mut spawned = commands.spawn((
+                        Replicated { server_id: *id },
+                        SimPosition(position),
+                        NetSamples(VecDeque::from([(snapshot.tick, position)])),
+                        Rendered,
+                    ));
+                    match &net.kind {
+                        NetKind::Ship { hull, police, name } => {
+                            spawned.insert((*hull, EName(name.clone())));
+                            if *police {
+                                spawned.insert(Police { system: Entity::from_bits(snapshot.system.unwrap_or_default()) });
+                            }
+                        }
+                        NetKind::Station { owner } => { spawned.insert((Station, Faction(*owner))); }
+                    }
+                    entities.insert(*id, spawned.id());
+                }
+
+                client.received.push_back((snapshot.tick, world));
+                while client.received.len() > RECEIVED_HISTORY {
+                    client.received.pop_front();
+                }
+                client.conn.send(&ClientMessage::Ack { tick: snapshot.tick });
+            }
+        }
+    }
+}
+
+/// Show entities a few ticks in the past, between the two samples around the clock
+fn interpolate_positions(time: Res<Time>,
+                         mut client: ResMut<NetClient>,
+                         mut query: Query<(&NetSamples, &mut SimPosition), With<Replicated>>) {
+    let Some(latest) = client.latest_tick() else { return; };
+    let target = latest as f64 - INTERPOLATION_DELAY;
+    let clock = client.clock.unwrap_or(target) + time.delta_seconds_f64() * client.tick_rate;
+    let clock = if (clock - target).abs() > MAX_CLOCK_DRIFT { target } else { clock };
+    client.clock = Some(clock);
+
+    for (samples, mut pos) in query.iter_mut() {
+        let after = samples.0.iter().position(|(t, _)| *t as f64 >= clock);
+        pos.0 = match after {
+            Some(0) => samples.0[0].1,
+            Some(i) => {
+                let (t0, p0) = samples.0[i - 1];
+                let (t1, p1) = samples.0[i];
+                let s = (clock - t0 as f64) / (t1 - t0).max(1) as f64;
+                p0.lerp(p1, s)
+            }
+            //no newer sample, hold the last one
+            None => samples.0.back().map_or(pos.0, |(_, p)| *p),
+        };
+    }
+}
+
+/// Orders go to the server with its entity ids, nothing is applied locally
+fn forward_orders(mut client: ResMut<NetClient>,
+                  mut orders: EventReader<OrderEvent>,
+                  replicated: Query<&Replicated>) {
+    let server_id = |entity: Entity| replicated.get(entity).ok().map(|r| r.server_id);
+    for ev in orders.iter() {
+        //docked pilots aren't replicated, undocking always means our own pilot
+        let ship = match ev.order {
+            ShipOrder::Undock => client.pilot,
+            _ => server_id(ev.ship),
+        };
+        let order = match ev.order {
+            ShipOrder::Dock(station) => server_id(station).map(NetOrder::Dock),
+            ShipOrder::Approach(target) => server_id(target).map(NetOrder::Approach),
+            ShipOrder::Orbit(target) => server_id(target).map(NetOrder::Orbit),
+            ShipOrder::Lock(target) => server_id(target).map(NetOrder::Lock),
+            ShipOrder::Fire(target) => server_id(target).map(NetOrder::Fire),
+            ShipOrder::Jump(gate) => server_id(gate).map(NetOrder::Jump),
+            other => Some(NetOrder::from(other)),
+        };
+        if let (Some(ship), Some(order)) = (ship, order) {
+            client.conn.send(&ClientMessage::Order { ship, order });
+        }
+    }
+}
+
+fn flush_client(mut client: ResMut<NetClient>) {
+    client.conn.flush();
+}
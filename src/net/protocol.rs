@@ -0,0 +1,314 @@
+//! Messages between server and clients, bincode frames prefixed by their length over TCP.
+//! Entities are referred to by their server side bits.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::space::orders::ShipOrder;
+use crate::space::ship::HullClass;
+
+/// Frames bigger than this are a broken or hostile peer
+const MAX_FRAME: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// First message, the pilot the client wants to fly
+    Hello { pilot_uid: u64 },
+    Order { ship: u64, order: NetOrder },
+    /// Last snapshot applied, the server builds the next deltas on it
+    Ack { tick: u64 },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Welcome { pilot: Option<u64>, tick: u64, tick_rate: f64 },
+    Snapshot(Snapshot),
+}
+
+/// [`ShipOrder`] with server entity bits
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NetOrder {
+    Navigate([f64; 3]),
+    Warp([f64; 3]),
+    Dock(u64),
+    Approach(u64),
+    Orbit(u64),
+    Lock(u64),
+    Fire(u64),
+    Jump(u64),
+    Stop,
+    Undock,
+}
+
+impl From<ShipOrder> for NetOrder {
+    fn from(order: ShipOrder) -> Self {
+        match order {
+            ShipOrder::Navigate(at) => NetOrder::Navigate(at),
+            ShipOrder::Warp(at) => NetOrder::Warp(at),
+            ShipOrder::Dock(station) => NetOrder::Dock(station.to_bits()),
+            ShipOrder::Approach(target) => NetOrder::Approach(target.to_bits()),
+            ShipOrder::Orbit(target) => NetOrder::Orbit(target.to_bits()),
+            ShipOrder::Lock(target) => NetOrder::Lock(target.to_bits()),
+            ShipOrder::Fire(target) => NetOrder::Fire(target.to_bits()),
+            ShipOrder::Jump(gate) => NetOrder::Jump(gate.to_bits()),
+            ShipOrder::Stop => NetOrder::Stop,
+            ShipOrder::Undock => NetOrder::Undock,
+        }
+    }
+}
+
+impl From<NetOrder> for ShipOrder {
+    fn from(order: NetOrder) -> Self {
+        match order {
+            NetOrder::Navigate(at) => ShipOrder::Navigate(at),
+            NetOrder::Warp(at) => ShipOrder::Warp(at),
+            NetOrder::Dock(station) => ShipOrder::Dock(Entity::from_bits(station)),
+            NetOrder::Approach(target) => ShipOrder::Approach(Entity::from_bits(target)),
+            NetOrder::Orbit(target) => ShipOrder::Orbit(Entity::from_bits(target)),
+            NetOrder::Lock(target) => ShipOrder::Lock(Entity::from_bits(target)),
+            NetOrder::Fire(target) => ShipOrder::Fire(Entity::from_bits(target)),
+            NetOrder::Jump(gate) => ShipOrder::Jump(Entity::from_bits(gate)),
+            NetOrder::Stop => ShipOrder::Stop,
+            NetOrder::Undock => ShipOrder::Undock,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NetKind {
+    Ship { hull: HullClass, police: bool, name: String },
+    Station { owner: u32 },
+}
+
+/// Replicated state of an entity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetEntity {
+    pub kind: NetKind,
+    pub position: [f64; 3],
+}
+
+/// Fields that changed since the baseline, everything is set for entities the baseline doesn't have
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EntityDelta {
+    pub id: u64,
+    pub kind: Option<NetKind>,
+    pub position: Option<[f64; 3]>,
+}
+
+/// Entities of the client's system at `tick`, relative to the snapshot of tick `baseline`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub baseline: Option<u64>,
+    pub system: Option<u64>,
+    pub entities: Vec<EntityDelta>,
+    pub removed: Vec<u64>,
+}
+
+pub type WorldState = HashMap<u64, NetEntity>;
+
+/// Delta turning `baseline` into `current`
+pub fn diff(baseline: &WorldState, current: &WorldState) -> (Vec<EntityDelta>, Vec<u64>) {
+    let entities = current.iter().filter_map(|(id, state)| {
+        let old = baseline.get(id);
+        let kind = (old.map(|o| &o.kind) != Some(&state.kind)).then(|| state.kind.clone());
+        let position = (old.map(|o| o.position) != Some(state.position)).then_some(state.position);
+        (kind.is_some() || position.is_some()).then_some(EntityDelta { id: *id, kind, position })
+    }).collect();
+    let removed = baseline.keys().filter(|id| !current.contains_key(id)).copied().collect();
+    (entities, removed)
+}
+
+/// Apply a delta made by [`diff`], `None` when a new entity misses a field
+pub fn patch(baseline: &WorldState, entities: &[EntityDelta], removed: &[u64]) -> Option<WorldState> {
+    let mut state = baseline.clone();
+    for id in removed {
+        state.remove(id);
+    }
+    for delta in entities {
+        match state.get_mut(&delta.id) {
+            Some(existing) => {
+                if let Some(kind) = &delta.kind {
+                    existing.kind = kind.clone();
+                }
+                if let Some(position) = delta.position {
+                    existing.position = position;
+                }
+            }
+            None => {
+                state.insert(delta.id, NetEntity {
+                    kind: delta.kind.clone()?,
+                    position: delta.position?,
+                });
+            }
+        }
+    }
+    Some(state)
+}
+
+/// Non blocking framed stream, `closed` once the peer is gone or misbehaved
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    pub closed: bool,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> std::io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream, incoming: Vec::new(), outgoing: Vec::new(), closed: false })
+    }
+
+    pub fn send<T: Serialize>(&mut self, message: &T) {
+        match bincode::serialize(message) {
+            Ok(bytes) => {
+                self.outgoing.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                self.outgoing.extend_from_slice(&bytes);
+            }
+            Err(e) => error!("could not encode message : {}", e),
+        }
+    }
+
+    /// Write as much as the socket takes, the rest waits for the next frame
+    pub fn flush(&mut self) {
+        while !self.outgoing.is_empty() && !self.closed {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => self.closed = true,
+                Ok(n) => { self.outgoing.drain(..n); }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => self.closed = true,
+            }
+        }
+    }
+
+    /// Every complete message received so far
+    pub fn receive<T: DeserializeOwned>(&mut self) -> Vec<T> {
+        let mut buffer = [0u8; 16 * 1024];
+        while !self.closed {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.closed = true,
+                Ok(n) => self.incoming.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => self.closed = true,
+            }
+        }
+
+        let mut messages = Vec::new();
+        while self.incoming.len() >= 4 {
+            let len = u32::from_le_bytes([self.incoming[0], self.incoming[1], self.incoming[2], self.incoming[3]]) as usize;
+            if len > MAX_FRAME {
+                self.closed = true;
+                break;
+            }
+            if self.incoming.len() < 4 + len {
+                break;
+            }
+            match bincode::deserialize(&self.incoming[4..4 + len]) {
+                Ok(message) => messages.push(message),
+                Err(e) => {
+                    warn!("dropping malformed message : {}", e);
+                }
+            }
+            self.incoming.drain(..4 + len);
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn ship(name: &str, position: [f64; 3]) -> NetEntity {
+        NetEntity { kind: NetKind::Ship { hull: HullClass::Frigate, police: false, name: name.to_string() }, position }
+    }
+
+    #[test]
+    fn patch_undoes_diff() {
+        let baseline = WorldState::from([
+            (1, ship("kept", [0.0, 0.0, 0.0])),
+            (2, ship("moved", [1.0, 0.0, 0.0])),
+            (3, ship("gone", [2.0, 0.0, 0.0])),
+            (4, NetEntity { kind: NetKind::Station { owner: 0 }, position: [3.0, 0.0, 0.0] }),
+        ]);
+        let mut current = baseline.clone();
+        current.get_mut(&2).unwrap().position = [1.0, 5.0, 0.0];
+        current.remove(&3);
+        current.get_mut(&4).unwrap().kind = NetKind::Station { owner: 1 };
+        current.insert(5, ship("new", [4.0, 0.0, 0.0]));
+
+        let (entities, removed) = diff(&baseline, &current);
+        assert_eq!(removed, vec![3]);
+        //unchanged entities and fields are left out
+        assert!(entities.iter().all(|d| d.id != 1));
+        let moved = entities.iter().find(|d| d.id == 2).unwrap();
+        assert!(moved.kind.is_none() && moved.position.is_some());
+
+        assert_eq!(patch(&baseline, &entities, &removed), Some(current.clone()));
+        //a full snapshot is a diff on nothing
+        let (entities, removed) = diff(&WorldState::new(), &current);
+        assert_eq!(patch(&WorldState::new(), &entities, &removed), Some(current));
+    }
+
+    #[test]
+    fn patch_refuses_incomplete_new_entity() {
+        let delta = EntityDelta { id: 7, kind: None, position: Some([0.0, 0.0, 0.0]) };
+        assert_eq!(patch(&WorldState::new(), &[delta], &[]), None);
+    }
+
+    #[test]
+    fn messages_cross_localhost() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = Connection::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap()).unwrap();
+        let mut server = Connection::new(listener.accept().unwrap().0).unwrap();
+
+        client.send(&ClientMessage::Hello { pilot_uid: 42 });
+        client.send(&ClientMessage::Order { ship: 3, order: NetOrder::Warp([1.0, 2.0, 3.0]) });
+        let received = exchange::<ClientMessage>(&mut client, &mut server, 2);
+        assert!(matches!(received[0], ClientMessage::Hello { pilot_uid: 42 }));
+        assert!(matches!(received[1], ClientMessage::Order { ship: 3, order } if order == NetOrder::Warp([1.0, 2.0, 3.0])));
+
+        let (entities, removed) = diff(&WorldState::new(), &WorldState::from([(1, ship("far", [1e9, 0.0, 0.0]))]));
+        server.send(&ServerMessage::Welcome { pilot: Some(9), tick: 10, tick_rate: 60.0 });
+        server.send(&ServerMessage::Snapshot(Snapshot { tick: 12, baseline: None, system: Some(5), entities, removed }));
+        let received = exchange::<ServerMessage>(&mut server, &mut client, 2);
+        assert!(matches!(received[0], ServerMessage::Welcome { pilot: Some(9), tick: 10, .. }));
+        let ServerMessage::Snapshot(snapshot) = &received[1] else { panic!("expected a snapshot") };
+        assert_eq!(patch(&WorldState::new(), &snapshot.entities, &snapshot.removed).map(|w| w.len()), Some(1));
+
+        //the client notices the server leaving
+        drop(server);
+        for _ in 0..200 {
+            if !client.receive::<ServerMessage>().is_empty() || client.closed {
+                break;
+            }
+            sleep(Duration::from_millis(5));
+        }
+        assert!(client.closed);
+    }
+
+    /// Flush `from` until `to` got `count` messages, both sockets are non blocking
+    fn exchange<T: DeserializeOwned>(from: &mut Connection, to: &mut Connection, count: usize) -> Vec<T> {
+        let mut received = Vec::new();
+        for _ in 0..200 {
+            from.flush();
+            received.extend(to.receive::<T>());
+            if received.len() >= count {
+                break;
+            }
+            sleep(Duration::from_millis(5));
+        }
+        assert_eq!(received.len(), count);
+        received
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+use std::net::{SocketAddr, TcpListener};
+
+use bevy::prelude::*;
+
+use crate::base::timer::SimTick;
+use crate::space::galaxy::{GalaxyCoordinate, SimPosition};
+use crate::space::orders::{Docked, OrderEvent, OrderSource};
+use crate::space::pilot::{EName, Faction, Pilot, PilotIndex};
+use crate::space::security::Police;
+use crate::space::ship::{HullClass, UndockingFrom};
+use crate::space::station::Station;
+
+use super::exit_app;
+use super::protocol::{ClientMessage, Connection, diff, NetEntity, NetKind, ServerMessage, Snapshot, WorldState};
+
+/// Ticks between two snapshots
+const SNAPSHOT_INTERVAL: u64 = 3;
+/// Snapshots kept per client to build deltas on, older acks get a full snapshot
+const SNAPSHOT_HISTORY: usize = 32;
+
+/// Authoritative simulation, clients only send orders
+pub struct ServerPlugin {
+    pub addr: SocketAddr,
+    /// Sim ticks per second, sent to clients for interpolation
+    pub tick_rate: f64,
+}
+
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        let listener = match TcpListener::bind(self.addr).and_then(|l| l.set_nonblocking(true).map(|_| l)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("cannot listen on {} : {}", self.addr, e);
+                app.add_startup_system(exit_app);
+                return;
+            }
+        };
+        info!("server listening on {}", self.addr);
+
+        app
+            .insert_resource(NetServer { listener, clients: Vec::new(), tick_rate: self.tick_rate })
+            .add_system_to_stage(CoreStage::PreUpdate, receive_client_messages)
+            .add_system_to_stage(CoreStage::Last, send_snapshots);
+    }
+}
+
+struct ClientSession {
+    conn: Connection,
+    pilot: Option<Entity>,
+    acked: Option<u64>,
+    sent: VecDeque<(u64, WorldState)>,
+}
+
+#[derive(Resource)]
+pub struct NetServer {
+    listener: TcpListener,
+    clients: Vec<ClientSession>,
+    tick_rate: f64,
+}
+
+impl NetServer {
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+}
+
+fn receive_client_messages(mut server: ResMut<NetServer>,
+                           tick: Res<SimTick>,
+                           index: Res<PilotIndex>,
+                           mut orders: EventWriter<OrderEvent>) {
+    while let Ok((stream, addr)) = server.listener.accept() {
+        match Connection::new(stream) {
+            Ok(conn) => {
+                info!("client connected from {}", addr);
+                server.clients.push(ClientSession { conn, pilot: None, acked: None, sent: VecDeque::new() });
+            }
+            Err(e) => warn!("could not set up client {} : {}", addr, e),
+        }
+    }
+
+    let tick_rate = server.tick_rate;
+    for client in server.clients.iter_mut() {
+        for message in client.conn.receive::<ClientMessage>() {
+            match message {
+                ClientMessage::Hello { pilot_uid } => {
+                    client.pilot = index.get(pilot_uid);
+                    client.conn.send(&ServerMessage::Welcome {
+                        pilot: client.pilot.map(|p| p.to_bits()),
+                        tick: tick.0,
+                        tick_rate,
+                    });
+                }
+                //a client only ever commands its own pilot
+                ClientMessage::Order { ship, order } => {
+                    if client.pilot.map(|p| p.to_bits()) == Some(ship) {
+                        orders.send(OrderEvent { ship: Entity::from_bits(ship), order: order.into(), source: OrderSource::Player });
+                    }
+                }
+                ClientMessage::Ack { tick } => {
+                    client.acked = Some(client.acked.map_or(tick, |a| a.max(tick)));
+                }
+            }
+        }
+    }
+    server.clients.retain(|c| {
+        if c.conn.closed {
+            info!("client disconnected");
+        }
+        !c.conn.closed
+    });
+}
+
+/// Each client gets the entities of the system its pilot is in, as a delta on its last ack
+fn send_snapshots(mut server: ResMut<NetServer>,
+                  tick: Res<SimTick>,
+                  pilots: Query<(Option<&GalaxyCoordinate>, Option<&Docked>, Option<&UndockingFrom>), With<Pilot>>,
+                  coordinates: Query<&GalaxyCoordinate>,
+                  replicated: Query<(Entity, &SimPosition, &GalaxyCoordinate, Option<&HullClass>, Option<&Police>, Option<&EName>, Option<&Station>, Option<&Faction>)>) {
+    if !tick.0.is_multiple_of(SNAPSHOT_INTERVAL) {
+        for client in server.clients.iter_mut() {
+            client.conn.flush();
+        }
+        return;
+    }
+
+    for client in server.clients.iter_mut() {
+        //docked pilots see the system of their station
+        let system = client.pilot.and_then(|p| pilots.get(p).ok()).and_then(|(coord, docked, undocking)| {
+            coord.map(|c| c.0)
+                .or_else(|| docked.and_then(|d| coordinates.get(d.0).ok()).map(|c| c.0))
+                .or_else(|| undocking.and_then(|u| coordinates.get(u.0).ok()).map(|c| c.0))
+        });
+
+        let mut current = WorldState::new();
+        if let Some(system) = system {
+            for (entity, pos, coord, hull, police, name, station, faction) in replicated.iter() {
+                if coord.0 != system {
+                    continue;
+                }
+                let kind = match (hull, station) {
+                    (Some(hull), _) => NetKind::Ship {
+                        hull: *hull,
+                        police: police.is_some(),
+                        name: name.map_or(String::new(), |n| n.0.clone()),
+                    },
+                    (None, Some(_)) => NetKind::Station { owner: faction.map_or(0, |f| f.0) },
+                    (None, None) => continue,
+                };
+                current.insert(entity.to_bits(), NetEntity { kind, position: pos.0.to_array() });
+            }
+        }
+
+        let baseline = client.acked.and_then(|acked| client.sent.iter().find(|(t, _)| *t == acked));
+        let empty = WorldState::new();
+        let (entities, removed) = diff(baseline.map_or(&empty, |(_, s)| s), &current);
+        client.conn.send(&ServerMessage::Snapshot(Snapshot {
+            tick: tick.0,
+            baseline: baseline.map(|(t, _)| *t),
+            system: system.map(|s| s.to_bits()),
+            entities,
+            removed,
+        }));
+        client.conn.flush();
+
+        client.sent.push_back((tick.0, current));
+        while client.sent.len() > SNAPSHOT_HISTORY {
+            client.sent.pop_front();
+        }
+    }
+}
@@ -1,15 +1,51 @@
 use bevy::app::{App, PluginGroupBuilder};
 use bevy::prelude::*;
-use bevy::prelude::system_adapter::new;
-use crate::space::project::project_to_camera;
+use crate::base::rng::SimRng;
+use crate::base::camera::CameraSystem;
+use crate::base::velocity::VelocitySystem;
+use crate::space::orders::OrderSystem;
+use crate::space::progression::ProgressionSystem;
+use crate::space::skills::SkillSystem;
+use crate::space::project::{project_to_camera, rebase_camera_origin, ProjectionSystem};
 
+use self::activity::ActivityPlugin;
+use self::combat::CombatPlugin;
+use self::context_menu::ContextMenuPlugin;
+use self::dev_commands::DevCommandsPlugin;
+use self::faction::FactionPlugin;
+use self::mining::MiningPlugin;
 use self::galaxy::*;
+use self::orders::OrdersPlugin;
+use self::overview::OverviewPlugin;
+use self::pilot::PilotPlugin;
+use self::presentation::PresentationPlugin;
+use self::progression::ProgressionPlugin;
+use self::save::SavePlugin;
+use self::security::SecurityPlugin;
+use self::selection::SelectionPlugin;
 use self::ship::*;
+use self::skills::SkillPlugin;
 
+pub mod activity;
+pub mod cargo;
+pub mod combat;
+pub mod context_menu;
+pub mod dev_commands;
+pub mod faction;
 pub mod ship;
 pub mod pilot;
+pub mod presentation;
 pub mod galaxy;
+pub mod mining;
+pub mod orders;
+pub mod overview;
+pub mod progression;
 pub mod project;
+pub mod replay;
+pub mod save;
+pub mod security;
+pub mod selection;
+pub mod skills;
 pub mod station;
 
 pub struct SpaceGamePlugins;
@@ -18,7 +54,23 @@ impl PluginGroup for SpaceGamePlugins {
     fn build(self) -> PluginGroupBuilder {
         PluginGroupBuilder::start::<Self>()
             .add(GalaxyPlugin)
+            .add(GalaxyViewPlugin)
+            .add(PresentationPlugin)
+            .add(OverviewPlugin)
+            .add(SelectionPlugin)
+            .add(ContextMenuPlugin)
             .add(ShipPlugins)
+            .add(PilotPlugin)
+            .add(ActivityPlugin)
+            .add(OrdersPlugin)
+            .add(CombatPlugin)
+            .add(MiningPlugin)
+            .add(FactionPlugin)
+            .add(SecurityPlugin)
+            .add(SkillPlugin)
+            .add(ProgressionPlugin)
+            .add(SavePlugin)
+            .add(DevCommandsPlugin)
     }
 }
 
@@ -26,21 +78,33 @@ pub struct GalaxyPlugin;
 impl Plugin for GalaxyPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_state(ViewState::GALAXY)
             .insert_resource(SystemMap(Vec::new()))
-            .insert_resource(GalaxyScale(0.000001))
+            .insert_resource(GalaxyScale::default());
+    }
+}
+
+/// Galaxy and system views, needs rendering, picking and the camera
+pub struct GalaxyViewPlugin;
+impl Plugin for GalaxyViewPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_state(ViewState::GALAXY)
             .add_event::<HideGalaxyEvent>()
             .add_event::<HideSystemEvent>()
             .add_event::<RenderGalaxyEvent>()
             .add_event::<RenderSystemEvent>()
-            .add_system(project_to_camera)
+            .add_system(rebase_camera_origin
+                .label(ProjectionSystem::Rebase)
+                .after(CameraSystem::Move))
+            .add_system(project_to_camera.after(ProjectionSystem::Rebase))
             .add_system(exit_system_view)
             .add_system(click_enter_system_view)
             .add_system(hide_galaxy_view)
             .add_system(hide_system_view)
             .add_system(flag_render_solar_system)
             .add_system(generate_galaxy_view)
-            .add_system(generate_system_view);
+            .add_system(generate_system_view)
+            .add_system(attach_solar_system_display);
     }
 }
 
@@ -48,8 +112,14 @@ pub struct ShipPlugins;
 impl Plugin for ShipPlugins {
     fn build(&self, app: &mut App) {
         app
-            .add_system(compute_ship_forces)
-            .add_system(undock_pilot_system);
+            .init_resource::<SimRng>()
+            .add_system(compute_ship_forces
+                .label(ShipSystem::Forces)
+                .after(SkillSystem::Bonuses)
+                .after(OrderSystem::Follow)
+                .before(VelocitySystem::Apply))
+            .add_system(undock_pilot_system.after(SkillSystem::Bonuses).before(ShipSystem::Forces))
+            .add_system(upgrade_hull_on_level_up.after(ProgressionSystem::Award));
     }
 }
 
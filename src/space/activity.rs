@@ -0,0 +1,27 @@
+use bevy::prelude::*;
+
+/// Gameplay events emitted by pilots doing things in the universe.
+/// Other modules (standings, progression...) react to them, so the emitter
+/// doesn't have to know who is listening.
+pub struct ActivityPlugin;
+
+impl Plugin for ActivityPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<PilotKilledEvent>()
+            .add_event::<OreMinedEvent>();
+    }
+}
+
+/// A pilot's ship got destroyed by another pilot
+pub struct PilotKilledEvent {
+    pub victim: Entity,
+    pub killer: Entity,
+}
+
+/// A pilot transferred ore from an asteroid to its cargo
+pub struct OreMinedEvent {
+    pub pilot: Entity,
+    /// Volume in m3
+    pub volume: f64,
+}
@@ -0,0 +1,29 @@
+//! What pilots carry. Holds have no size limit yet and nothing buys or sells items.
+
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum Item {
+    Ore,
+}
+
+impl Item {
+    pub const ALL: [Item; 1] = [Item::Ore];
+}
+
+/// Volume of each item a pilot holds, in m3, kept while docked
+#[derive(Component, Debug, Clone, Default)]
+pub struct Cargo(pub BTreeMap<Item, f64>);
+
+impl Cargo {
+    pub fn add(&mut self, item: Item, volume: f64) {
+        *self.0.entry(item).or_default() += volume;
+    }
+
+    pub fn volume(&self, item: Item) -> f64 {
+        self.0.get(&item).copied().unwrap_or_default()
+    }
+}
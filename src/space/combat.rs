@@ -0,0 +1,88 @@
+//! Ships engaging their locked target shoot it while it is within [`WEAPON_RANGE`]. Damage eats
+//! the shield, then the armor, then the structure. A ship left without structure is destroyed,
+//! the killer gets a [`PilotKilledEvent`] and the victim relaunches from its respawn base.
+
+use bevy::prelude::*;
+
+use crate::base::timer::SimClock;
+use crate::base::units::{GalaxyScale, Metres};
+use crate::base::velocity::PlaneLock;
+use crate::space::activity::PilotKilledEvent;
+use crate::space::galaxy::{GalaxyCoordinate, Rendered, SimPosition};
+use crate::space::orders::{DockingAt, Following, OrderSystem, Warping};
+use crate::space::pilot::{Pilot, RespawnBase};
+use crate::space::security::Police;
+use crate::space::ship::{Health, HullClass, ShipBundle, TargetLock, UndockingFrom};
+use crate::space::skills::SkillBonuses;
+
+/// Weapons reach anything sensors can lock
+pub const WEAPON_RANGE: Metres = Metres(150_000.0);
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(fire_weapons.label(CombatSystem::Fire).after(OrderSystem::Warp))
+            //after everyone had the frame to read the kills
+            .add_system_to_stage(CoreStage::PostUpdate, destroy_ships);
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum CombatSystem {
+    Fire,
+}
+
+/// Shooting at the [`TargetLock`], a lock alone doesn't fire
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Engaging;
+
+fn fire_weapons(clock: Res<SimClock>,
+                scale: Res<GalaxyScale>,
+                attackers: Query<(Entity, &Pilot, &TargetLock, &SimPosition, &GalaxyCoordinate, &HullClass, Option<&SkillBonuses>), With<Engaging>>,
+                mut targets: Query<(&SimPosition, &GalaxyCoordinate, &mut Health)>,
+                mut kills: EventWriter<PilotKilledEvent>) {
+    let range = scale.to_sim(WEAPON_RANGE).0;
+    let dt = clock.delta_seconds_f64() as f32;
+    //in pilot order, the kill goes to the same shooter whatever the entity layout
+    let mut attackers: Vec<_> = attackers.iter().collect();
+    attackers.sort_by_key(|(_, pilot, ..)| pilot.u_id);
+    for (entity, _, lock, pos, coord, hull, bonuses) in attackers {
+        let Ok((t_pos, t_coord, mut health)) = targets.get_mut(lock.0) else { continue; };
+        if t_coord.0 != coord.0 || t_pos.0.distance(pos.0) > range || health.is_destroyed() {
+            continue;
+        }
+        health.damage(hull.weapon_dps() * bonuses.map_or(1.0, |b| b.weapon_damage) * dt);
+        //the last shot takes the kill
+        if health.is_destroyed() {
+            kills.send(PilotKilledEvent { victim: lock.0, killer: entity });
+        }
+    }
+}
+
+/// Police ships vanish, other pilots get a new ship at their respawn base
+fn destroy_ships(mut commands: Commands,
+                 mut kills: EventReader<PilotKilledEvent>,
+                 victims: Query<(&RespawnBase, Option<&Police>), With<HullClass>>) {
+    for ev in kills.iter() {
+        let Ok((respawn, police)) = victims.get(ev.victim) else { continue; };
+        match (respawn.0, police) {
+            (Some(base), None) => {
+                commands.entity(ev.victim)
+                    .remove::<ShipBundle>()
+                    .remove::<TargetLock>()
+                    .remove::<Engaging>()
+                    .remove::<DockingAt>()
+                    .remove::<Following>()
+                    .remove::<Warping>()
+                    .remove::<PlaneLock>()
+                    //drawn again once it is back in a rendered system
+                    .remove::<Rendered>()
+                    .insert(UndockingFrom(base));
+            }
+            _ => commands.entity(ev.victim).despawn_recursive(),
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! Right-click menu of the system view, on an entity or on empty space. Its entries order
+//! the selected ships and are greyed out, with the reason on hover, when none of them can comply.
+//! Ships sent to a point of space spread in [`formation`] around it.
+//! Jump is never enabled yet : neither the default setup nor saves spawn gates.
+
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use bevy_mod_picking::Selection;
+
+use crate::base::actions::{Action, ActionState};
+use crate::base::camera::{CameraFollow, CameraID, CameraOrigin};
+use crate::base::units::{GalaxyScale, Metres, SimUnits};
+use crate::space::faction::Standings;
+use crate::space::galaxy::{GateDestination, Rendered, SimPosition, SolarSystem, ViewState};
+use crate::space::orders::{JUMP_RANGE, LOCK_RANGE, OrderEvent, OrderSource, ShipOrder, WARP_MIN_RANGE};
+use crate::space::overview::format_distance;
+use crate::space::pilot::{Faction, Pilot};
+use crate::space::project::ViewCursor;
+use crate::space::selection::{formation, pick};
+use crate::space::ship::HullClass;
+use crate::space::station::Station;
+
+/// Ships closer than this have nothing left to approach
+const APPROACH_MIN: Metres = Metres(1_000.0);
+/// Ships warping to an entity drop out this short of it, within docking and jump range
+const WARP_LANDING: Metres = Metres(2_000.0);
+
+pub struct ContextMenuPlugin;
+
+impl Plugin for ContextMenuPlugin {
+    fn build(&self, app: &mut App) {
+        crate::base::ensure_egui(app);
+        app
+            .init_resource::<ContextMenu>()
+            .add_system(open_context_menu)
+            .add_system(context_menu_window.after(open_context_menu))
+            .add_system(look_at.after(context_menu_window));
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum MenuTarget {
+    /// Sim position on the plane of the camera
+    Space(DVec2),
+    Entity(Entity),
+}
+
+#[derive(Resource, Default)]
+pub struct ContextMenu {
+    /// Where the menu is drawn, in egui points, and what it was opened on
+    pub open: Option<(egui::Pos2, MenuTarget)>,
+    look_at: Option<DVec2>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum MenuEntry {
+    Approach,
+    Orbit,
+    WarpTo,
+    Dock,
+    Jump,
+    LockTarget,
+    LookAt,
+}
+
+impl MenuEntry {
+    const ALL: [MenuEntry; 7] = [
+        MenuEntry::Approach,
+        MenuEntry::Orbit,
+        MenuEntry::WarpTo,
+        MenuEntry::Dock,
+        MenuEntry::Jump,
+        MenuEntry::LockTarget,
+        MenuEntry::LookAt,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            MenuEntry::Approach => "Approach",
+            MenuEntry::Orbit => "Orbit",
+            MenuEntry::WarpTo => "Warp to",
+            MenuEntry::Dock => "Dock",
+            MenuEntry::Jump => "Jump",
+            MenuEntry::LockTarget => "Lock target",
+            MenuEntry::LookAt => "Look at",
+        }
+    }
+}
+
+struct SelectedShip<'a> {
+    entity: Entity,
+    pos: DVec3,
+    pilot: Option<(&'a Pilot, Faction)>,
+}
+
+struct TargetInfo {
+    entity: Entity,
+    pos: DVec3,
+    /// Owner of a station
+    station: Option<Faction>,
+    gate: bool,
+    ship: bool,
+}
+
+/// Orders of the ships able to comply, or why none of them can
+type EntryOrders = Result<Vec<(Entity, ShipOrder)>, String>;
+
+fn per_ship(ships: &[SelectedShip], order: impl Fn(&SelectedShip) -> Result<ShipOrder, String>) -> EntryOrders {
+    if ships.is_empty() {
+        return Err(String::from("no ship selected"));
+    }
+    let mut first_refusal = None;
+    let mut orders = Vec::new();
+    for ship in ships {
+        match order(ship) {
+            Ok(o) => orders.push((ship.entity, o)),
+            Err(reason) => { first_refusal.get_or_insert(reason); }
+        }
+    }
+    match first_refusal {
+        Some(reason) if orders.is_empty() => Err(reason),
+        _ => Ok(orders),
+    }
+}
+
+/// Orders on an entity are never given to the entity itself
+fn other_than<'a>(target: Option<&'a TargetInfo>, ship: &SelectedShip) -> Result<&'a TargetInfo, String> {
+    match target {
+        Some(t) if t.entity == ship.entity => Err(String::from("can't target itself")),
+        Some(t) => Ok(t),
+        None => Err(String::from("needs a target")),
+    }
+}
+
+fn entry_orders(entry: MenuEntry,
+                target: Option<&TargetInfo>,
+                space: DVec2,
+                ships: &[SelectedShip],
+                scale: &GalaxyScale,
+                standings: &Standings) -> EntryOrders {
+    let distance = |ship: &SelectedShip, at: DVec3| scale.to_metres(SimUnits(ship.pos.distance(at)));
+    let other = |ship: &SelectedShip| other_than(target, ship);
+
+    match entry {
+        MenuEntry::Approach if target.is_none() => {
+            let at: Vec<DVec3> = ships.iter().map(|s| s.pos).collect();
+            let slots = formation(space, &at, scale);
+            per_ship(ships, |ship| {
+                let slot = ships.iter().position(|s| s.entity == ship.entity).map_or(space, |i| slots[i]);
+                Ok(ShipOrder::Navigate([slot.x, slot.y, ship.pos.z]))
+            })
+        }
+        MenuEntry::Approach => per_ship(ships, |ship| {
+            let t = other(ship)?;
+            if distance(ship, t.pos) < APPROACH_MIN {
+                return Err(format!("already within {}", format_distance(APPROACH_MIN)));
+            }
+            Ok(ShipOrder::Approach(t.entity))
+        }),
+        MenuEntry::Orbit => per_ship(ships, |ship| other(ship).map(|t| ShipOrder::Orbit(t.entity))),
+        MenuEntry::WarpTo => per_ship(ships, |ship| {
+            let at = match target {
+                Some(_) => {
+                    let t = other(ship)?;
+                    t.pos + (ship.pos - t.pos).normalize_or_zero() * scale.to_sim(WARP_LANDING).0
+                }
+                None => space.extend(ship.pos.z),
+            };
+            if distance(ship, at) < WARP_MIN_RANGE {
+                return Err(format!("closer than {}", format_distance(WARP_MIN_RANGE)));
+            }
+            Ok(ShipOrder::Warp(at.to_array()))
+        }),
+        MenuEntry::Dock => per_ship(ships, |ship| {
+            let t = other(ship)?;
+            let Some(owner) = t.station else { return Err(String::from("not a station")); };
+            match ship.pilot {
+                Some((pilot, faction)) if !standings.can_dock(owner, pilot, faction) => Err(String::from("docking refused")),
+                _ => Ok(ShipOrder::Dock(t.entity)),
+            }
+        }),
+        MenuEntry::Jump => per_ship(ships, |ship| {
+            let t = other(ship)?;
+            if !t.gate {
+                return Err(String::from("not a gate"));
+            }
+            if distance(ship, t.pos) > JUMP_RANGE {
+                return Err(format!("further than {}", format_distance(JUMP_RANGE)));
+            }
+            Ok(ShipOrder::Jump(t.entity))
+        }),
+        MenuEntry::LockTarget => per_ship(ships, |ship| {
+            let t = other(ship)?;
+            if !t.ship {
+                return Err(String::from("not a ship"));
+            }
+            if distance(ship, t.pos) > LOCK_RANGE {
+                return Err(format!("further than {}", format_distance(LOCK_RANGE)));
+            }
+            Ok(ShipOrder::Lock(t.entity))
+        }),
+        //not an order, handled by the menu
+        MenuEntry::LookAt => Ok(Vec::new()),
+    }
+}
+
+/// Open the menu on the closest entity under the cursor, or on space
+fn open_context_menu(mut egui: ResMut<EguiContext>,
+                     mut menu: ResMut<ContextMenu>,
+                     actions: Res<ActionState>,
+                     view: Res<State<ViewState>>,
+                     cursor: ViewCursor,
+                     shown: Query<(Entity, &Transform), (With<Rendered>, Without<SolarSystem>, Without<Camera>)>) {
+    if *view.current() != ViewState::SYSTEM {
+        menu.open = None;
+        return;
+    }
+    if !actions.just_pressed(Action::ContextMenu) {
+        return;
+    }
+    let ctx = egui.ctx_mut();
+    if ctx.is_pointer_over_area() {
+        return;
+    }
+    let (Some(world), Some(pos)) = (cursor.world(), ctx.input().pointer.hover_pos()) else { return; };
+
+    let target = pick(world, shown.iter())
+        .map_or(MenuTarget::Space(cursor.to_sim(world)), MenuTarget::Entity);
+    menu.open = Some((pos, target));
+}
+
+fn context_menu_window(mut egui: ResMut<EguiContext>,
+                       mut menu: ResMut<ContextMenu>,
+                       scale: Res<GalaxyScale>,
+                       standings: Res<Standings>,
+                       mut orders: EventWriter<OrderEvent>,
+                       selected: Query<(Entity, &Selection, &SimPosition, Option<&Pilot>, Option<&Faction>), (With<Rendered>, With<HullClass>)>,
+                       targets: Query<(&SimPosition, Option<&Station>, Option<&Faction>, Option<&HullClass>, Option<&GateDestination>)>) {
+    let Some((pos, target)) = menu.open else { return; };
+
+    let target_info = match target {
+        MenuTarget::Space(_) => None,
+        MenuTarget::Entity(entity) => match targets.get(entity) {
+            Ok((t_pos, station, faction, hull, gate)) => Some(TargetInfo {
+                entity,
+                pos: t_pos.0,
+                station: station.and(faction.copied()),
+                gate: gate.is_some(),
+                ship: hull.is_some(),
+            }),
+            //gone since the click
+            Err(_) => {
+                menu.open = None;
+                return;
+            }
+        },
+    };
+    let space = match target {
+        MenuTarget::Space(at) => at,
+        MenuTarget::Entity(_) => target_info.as_ref().map_or(DVec2::ZERO, |t| t.pos.truncate()),
+    };
+    let ships: Vec<SelectedShip> = selected.iter()
+        .filter(|(_, selection, ..)| selection.selected())
+        .map(|(entity, _, ship_pos, pilot, faction)| SelectedShip {
+            entity,
+            pos: ship_pos.0,
+            pilot: pilot.zip(faction.copied()),
+        })
+        .collect();
+
+    let mut chosen = None;
+    let area = egui::Area::new("context_menu")
+        .fixed_pos(pos)
+        .order(egui::Order::Foreground)
+        .show(egui.ctx_mut(), |ui| {
+            egui::Frame::menu(ui.style()).show(ui, |ui| {
+                for entry in MenuEntry::ALL {
+                    let result = entry_orders(entry, target_info.as_ref(), space, &ships, &scale, &standings);
+                    let button = ui.add_enabled(result.is_ok(), egui::Button::new(entry.label()).frame(false));
+                    match result {
+                        Ok(entry_orders) if button.clicked() => chosen = Some((entry, entry_orders)),
+                        Err(reason) => { button.on_disabled_hover_text(reason); }
+                        _ => {}
+                    }
+                }
+            });
+        });
+
+    if let Some((entry, entry_orders)) = chosen {
+        if entry == MenuEntry::LookAt {
+            menu.look_at = Some(space);
+        }
+        orders.send_batch(entry_orders.into_iter()
+            .map(|(ship, order)| OrderEvent { ship, order, source: OrderSource::Player }));
+        menu.open = None;
+    } else if area.response.clicked_elsewhere() {
+        menu.open = None;
+    }
+}
+
+/// Center the camera on what the menu was asked to look at
+fn look_at(mut menu: ResMut<ContextMenu>,
+           camera_id: Res<CameraID>,
+           mut origin: ResMut<CameraOrigin>,
+           mut follow: ResMut<CameraFollow>,
+           mut cameras: Query<&mut Transform, With<Camera>>) {
+    let Some(at) = menu.look_at.take() else { return; };
+    origin.0.x = at.x;
+    origin.0.y = at.y;
+    follow.target = None;
+    if let Ok(mut camera) = cameras.get_mut(camera_id.0) {
+        camera.translation.x = 0.0;
+        camera.translation.y = 0.0;
+    }
+}
@@ -0,0 +1,265 @@
+//! Console commands poking at the universe, for testing and debugging.
+//! Those changing the simulation go through [`apply_dev_action`] so recordings replay them.
+
+use bevy::ecs::world::EntityMut;
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::base::console::{ArgKind, Args, CommandResult, ConsoleCommand, ConsoleCommands};
+use crate::base::timer::SimClock;
+use crate::base::units::{GalaxyScale, Metres, SimUnits};
+use crate::base::velocity::{PlaneLock, Velocity};
+use crate::space::cargo::{Cargo, Item};
+use crate::space::faction::{FactionRegistry, Standings};
+use crate::space::galaxy::{AnomalyMining, GalaxyCoordinate, Rendered, SimPosition, SystemMap};
+use crate::space::pilot::{EName, Faction, PilotNameGenerator, spawn_new_pilot};
+use crate::space::security::Police;
+use crate::space::ship::{Destination, DestoType, HullClass, new_ship};
+use crate::space::station::Station;
+
+const HULLS: &[&str] = &["frigate", "destroyer", "cruiser", "battleship"];
+const STANDING_TARGETS: &[&str] = &["faction", "pilot"];
+const ITEMS: &[&str] = &["ore"];
+
+pub struct DevCommandsPlugin;
+
+impl Plugin for DevCommandsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DevActionEvent>();
+        let Some(mut commands) = app.world.get_resource_mut::<ConsoleCommands>() else { return; };
+        commands.register(ConsoleCommand::new("spawn_ship", "spawn a new pilot in a ship, next to the station of the system", spawn_ship)
+            .arg("system", ArgKind::Int)
+            .arg("hull", ArgKind::Choice(HULLS)).optional()
+            .arg("faction", ArgKind::Int).optional());
+        commands.register(ConsoleCommand::new("teleport", "move a ship to a system, at a position in metres", teleport)
+            .arg("ship", ArgKind::Entity)
+            .arg("system", ArgKind::Int)
+            .arg("x", ArgKind::Float).optional()
+            .arg("y", ArgKind::Float).optional()
+            .arg("z", ArgKind::Float).optional());
+        commands.register(ConsoleCommand::new("plane_lock", "keep a ship on the plane at a height in metres, unlock it without one", plane_lock)
+            .arg("ship", ArgKind::Entity)
+            .arg("z", ArgKind::Float).optional());
+        commands.register(ConsoleCommand::new("give", "put items in the cargo of a pilot, volume in m3", give)
+            .arg("pilot", ArgKind::Entity)
+            .arg("item", ArgKind::Choice(ITEMS))
+            .arg("volume", ArgKind::Float));
+        commands.register(ConsoleCommand::new("standing", "set the standing of a faction towards a faction or a pilot uid", set_standing)
+            .arg("from", ArgKind::Int)
+            .arg("towards", ArgKind::Choice(STANDING_TARGETS))
+            .arg("target", ArgKind::Int)
+            .arg("value", ArgKind::Float));
+        commands.register(ConsoleCommand::new("list", "list the entities of a system", list_entities)
+            .arg("system", ArgKind::Int));
+        commands.register(ConsoleCommand::new("timescale", "set how many sim seconds pass per real second", set_time_scale)
+            .arg("scale", ArgKind::Float));
+        commands.register(ConsoleCommand::new("pause", "pause or resume the simulation", toggle_pause));
+        commands.register(ConsoleCommand::new("clock", "show the game date and speed", show_clock));
+    }
+}
+
+/// Change made by a dev command, the arguments are already checked
+#[derive(Debug, Copy, Clone)]
+pub enum DevAction {
+    SpawnShip { system: Entity, hull: HullClass, faction: Faction },
+    /// `at` in sim units
+    Teleport { ship: Entity, system: Entity, at: DVec3 },
+    /// Height in sim units, `None` unlocks
+    PlaneLock { ship: Entity, z: Option<f64> },
+    /// Volume in m3
+    Give { pilot: Entity, item: Item, volume: f64 },
+    Standing { from: Faction, towards: StandingTarget, value: f32 },
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum StandingTarget {
+    Faction(Faction),
+    /// Pilot `u_id`
+    Pilot(u64),
+}
+
+/// Sent once a [`DevAction`] is applied
+pub struct DevActionEvent(pub DevAction);
+
+/// Systems are given by their index in the [`SystemMap`]
+fn system_arg(world: &World, index: i64) -> Result<Entity, String> {
+    let map = world.resource::<SystemMap>();
+    usize::try_from(index).ok()
+        .and_then(|i| map.0.get(i).copied())
+        .ok_or_else(|| format!("no system {}, there are {}", index, map.0.len()))
+}
+
+fn faction_arg(world: &World, index: i64) -> Result<Faction, String> {
+    let faction = Faction(u32::try_from(index).map_err(|_| format!("no faction {}", index))?);
+    world.resource::<FactionRegistry>().get(faction)
+        .map(|_| faction)
+        .ok_or_else(|| format!("no faction {}", index))
+}
+
+fn hull_by_name(name: &str) -> Option<HullClass> {
+    HULLS.iter().position(|h| *h == name).map(|i| HullClass::ALL[i])
+}
+
+fn item_by_name(name: &str) -> Option<Item> {
+    ITEMS.iter().position(|i| *i == name).map(|i| Item::ALL[i])
+}
+
+fn spawn_ship(world: &mut World, args: &Args) -> CommandResult {
+    let system = system_arg(world, args.int(0).unwrap_or_default())?;
+    let hull = args.word(1).and_then(hull_by_name).unwrap_or(HullClass::Frigate);
+    let faction = faction_arg(world, args.int(2).unwrap_or_default())?;
+    apply_dev_action(world, DevAction::SpawnShip { system, hull, faction })
+}
+
+fn teleport(world: &mut World, args: &Args) -> CommandResult {
+    let ship = args.entity(0).ok_or_else(|| String::from("no ship given"))?;
+    let system = system_arg(world, args.int(1).unwrap_or_default())?;
+    let scale = *world.resource::<GalaxyScale>();
+    let at = DVec3::new(
+        scale.to_sim(Metres(args.float(2).unwrap_or_default())).0,
+        scale.to_sim(Metres(args.float(3).unwrap_or_default())).0,
+        scale.to_sim(Metres(args.float(4).unwrap_or_default())).0,
+    );
+    apply_dev_action(world, DevAction::Teleport { ship, system, at })
+}
+
+fn plane_lock(world: &mut World, args: &Args) -> CommandResult {
+    let ship = args.entity(0).ok_or_else(|| String::from("no ship given"))?;
+    let scale = *world.resource::<GalaxyScale>();
+    let z = args.float(1).map(|z| scale.to_sim(Metres(z)).0);
+    apply_dev_action(world, DevAction::PlaneLock { ship, z })
+}
+
+fn give(world: &mut World, args: &Args) -> CommandResult {
+    let pilot = args.entity(0).ok_or_else(|| String::from("no pilot given"))?;
+    let item = args.word(1).and_then(item_by_name).ok_or_else(|| String::from("no item given"))?;
+    let volume = args.float(2).unwrap_or_default();
+    if volume <= 0.0 {
+        return Err(String::from("the volume must be positive"));
+    }
+    apply_dev_action(world, DevAction::Give { pilot, item, volume })
+}
+
+fn set_standing(world: &mut World, args: &Args) -> CommandResult {
+    let from = faction_arg(world, args.int(0).unwrap_or_default())?;
+    let target = args.int(2).unwrap_or_default();
+    let towards = match args.word(1) {
+        Some("pilot") => StandingTarget::Pilot(u64::try_from(target).map_err(|_| format!("no pilot {}", target))?),
+        _ => StandingTarget::Faction(faction_arg(world, target)?),
+    };
+    let value = args.float(3).unwrap_or_default() as f32;
+    apply_dev_action(world, DevAction::Standing { from, towards, value })
+}
+
+/// Commands and replays change the world through here, and recordings see every change
+pub fn apply_dev_action(world: &mut World, action: DevAction) -> CommandResult {
+    let output = match action {
+        DevAction::SpawnShip { system, hull, faction } => {
+            let at = world.query_filtered::<(&SimPosition, &GalaxyCoordinate), With<Station>>()
+                .iter(world)
+                .find(|(_, coord)| coord.0 == system)
+                .map_or(DVec3::ZERO, |(pos, _)| pos.0);
+            let pilot = spawn_new_pilot(faction, &mut world.resource_mut::<PilotNameGenerator>());
+            let name = pilot.pilot_name.0.clone();
+            let entity = world.spawn((pilot, new_ship(system, SimPosition(at), DestoType::None, hull))).id();
+            format!("spawned {:?} '{}' in a {:?}", entity, name, hull)
+        }
+        DevAction::Teleport { ship, system, at } => {
+            let mut entity = ship_in_space(world, ship)?;
+            let left_system = entity.get::<GalaxyCoordinate>().is_none_or(|c| c.0 != system);
+            entity.insert((GalaxyCoordinate(system), SimPosition(at), Velocity::default(), Destination(DestoType::None)));
+            //the view only shows the system it was opened on
+            if left_system {
+                entity.remove::<Rendered>();
+            }
+            format!("{:?} moved to {:?}", ship, system)
+        }
+        DevAction::PlaneLock { ship, z: Some(z) } => {
+            let scale = *world.resource::<GalaxyScale>();
+            ship_in_space(world, ship)?.insert(PlaneLock(z));
+            format!("{:?} locked at z = {:.0} m", ship, scale.to_metres(SimUnits(z)).0)
+        }
+        DevAction::PlaneLock { ship, z: None } => {
+            ship_in_space(world, ship)?.remove::<PlaneLock>();
+            format!("{:?} unlocked", ship)
+        }
+        DevAction::Give { pilot, item, volume } => {
+            let mut cargo = world.get_mut::<Cargo>(pilot).ok_or_else(|| format!("{:?} is not a pilot", pilot))?;
+            cargo.add(item, volume);
+            format!("{:?} now holds {:.0} m3 of {:?}", pilot, cargo.volume(item), item)
+        }
+        DevAction::Standing { from, towards: StandingTarget::Faction(to), value } => {
+            world.resource_mut::<Standings>().set_faction(from, to, value);
+            format!("standing of faction {} towards faction {} set to {}", from.0, to.0, value)
+        }
+        DevAction::Standing { from, towards: StandingTarget::Pilot(u_id), value } => {
+            world.resource_mut::<Standings>().set_pilot(from, u_id, value);
+            format!("standing of faction {} towards pilot {} set to {}", from.0, u_id, value)
+        }
+    };
+    world.resource_mut::<Events<DevActionEvent>>().send(DevActionEvent(action));
+    Ok(output)
+}
+
+fn ship_in_space(world: &mut World, ship: Entity) -> Result<EntityMut<'_>, String> {
+    let entity = world.get_entity_mut(ship).ok_or_else(|| format!("no entity {:?}", ship))?;
+    if !entity.contains::<HullClass>() {
+        return Err(format!("{:?} is not a ship in space", ship));
+    }
+    Ok(entity)
+}
+
+fn list_entities(world: &mut World, args: &Args) -> CommandResult {
+    let system = system_arg(world, args.int(0).unwrap_or_default())?;
+    let scale = *world.resource::<GalaxyScale>();
+    let mut query = world.query::<(Entity, &GalaxyCoordinate, &SimPosition, Option<&EName>, Option<&HullClass>,
+                                   Option<&Station>, Option<&Police>, Option<&AnomalyMining>)>();
+    let mut lines: Vec<(Entity, String)> = query.iter(world)
+        .filter(|(_, coord, ..)| coord.0 == system)
+        .map(|(entity, _, pos, name, hull, station, police, field)| {
+            let kind = match (hull, station, police, field) {
+                (_, Some(_), ..) => String::from("station"),
+                (Some(hull), _, Some(_), _) => format!("police {:?}", hull),
+                (Some(hull), _, None, _) => format!("{:?}", hull),
+                (.., Some(_)) => String::from("ore field"),
+                _ => String::from("?"),
+            };
+            let km = pos.0.to_array().map(|c| scale.to_metres(SimUnits(c)).0 / 1000.0);
+            let name = name.map_or(String::new(), |n| format!(" '{}'", n.0));
+            (entity, format!("{:?} {}{} at ({:.0}, {:.0}, {:.0}) km", entity, kind, name, km[0], km[1], km[2]))
+        })
+        .collect();
+    if lines.is_empty() {
+        return Ok(String::from("nothing in this system"));
+    }
+    lines.sort_by_key(|(entity, _)| entity.index());
+    Ok(lines.into_iter().map(|(_, line)| line).collect::<Vec<_>>().join("\n"))
+}
+
+fn set_time_scale(world: &mut World, args: &Args) -> CommandResult {
+    let scale = args.float(0).unwrap_or(1.0);
+    if !(0.0..=1000.0).contains(&scale) {
+        return Err(String::from("the scale goes from 0 to 1000"));
+    }
+    let mut clock = world.resource_mut::<SimClock>();
+    if clock.fixed_step.is_some() {
+        return Err(String::from("the clock runs on a fixed step, change the tick rate instead"));
+    }
+    clock.time_scale = scale;
+    Ok(format!("time scale set to {}", scale))
+}
+
+fn toggle_pause(world: &mut World, _args: &Args) -> CommandResult {
+    let mut clock = world.resource_mut::<SimClock>();
+    if clock.fixed_step.is_some() {
+        return Err(String::from("the clock runs on a fixed step and can't pause"));
+    }
+    clock.paused = !clock.paused;
+    Ok(String::from(if clock.paused { "paused" } else { "resumed" }))
+}
+
+fn show_clock(world: &mut World, _args: &Args) -> CommandResult {
+    let clock = world.resource::<SimClock>();
+    Ok(format!("{}, x{}{}, {:.0}s since the epoch",
+               clock.date(), clock.time_scale, if clock.paused { " paused" } else { "" }, clock.elapsed().as_secs_f64()))
+}
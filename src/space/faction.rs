@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::base::scheduler::{Job, JobClock, job_due, Scheduler};
+use crate::space::activity::PilotKilledEvent;
+use crate::space::combat::{CombatSystem, Engaging};
+use crate::base::units::Metres;
+use crate::space::galaxy::{GalaxyCoordinate, GalaxyScale, SimPosition};
+use crate::space::pilot::{Faction, NameLexicon, Pilot};
+use crate::space::orders::{OrderEvent, OrderSource, OrderSystem, ShipOrder};
+use crate::space::security::Police;
+use crate::space::ship::TargetLock;
+
+/// Standings are kept in [-10, 10]
+pub const STANDING_MIN: f32 = -10.0;
+pub const STANDING_MAX: f32 = 10.0;
+/// At or below this value, the other side is shot on sight and refused docking
+pub const HOSTILE_THRESHOLD: f32 = -5.0;
+/// At or above this value, the other side is considered an ally
+pub const FRIENDLY_THRESHOLD: f32 = 5.0;
+
+const KILL_PENALTY: f32 = 0.5;
+const KILL_REWARD: f32 = 0.1;
+
+/// Distance under which an NPC will pick a hostile as target
+const AGGRESSION_RANGE: Metres = Metres(150_000.0);
+/// Every NPC looks for targets once a second, a part of them each frame
+const NPC_TARGETING_JOB: &str = "npc_targeting";
+const NPC_TARGETING_SLOTS: u32 = 4;
+
+pub struct FactionPlugin;
+
+impl Plugin for FactionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(FactionRegistry::default())
+            .insert_resource(Standings::default())
+            .add_event::<StandingChangeEvent>()
+            .add_system(standings_from_activity.label(StandingSystem::FromActivity).after(CombatSystem::Fire))
+            .add_system(apply_standing_changes.after(StandingSystem::FromActivity))
+            .add_system(npc_acquire_targets
+                .with_run_criteria(job_due(NPC_TARGETING_JOB))
+                .before(OrderSystem::Apply));
+        app.world.get_resource_or_insert_with(Scheduler::default)
+            .add(NPC_TARGETING_JOB, Job::every(JobClock::Sim, Duration::from_secs(1)).staggered(NPC_TARGETING_SLOTS));
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum StandingSystem {
+    FromActivity,
+}
+
+/// Static description of a faction
+pub struct FactionDef {
+    pub name: String,
+    pub home_systems: Vec<Entity>,
+    pub color: Color,
+    /// What its pilots are named from
+    pub lexicon: NameLexicon,
+}
+
+/// Every known faction, indexed by the value stored in [`Faction`]
+#[derive(Resource, Default)]
+pub struct FactionRegistry(pub Vec<FactionDef>);
+
+impl FactionRegistry {
+    pub fn register(&mut self, def: FactionDef) -> Faction {
+        self.0.push(def);
+        Faction((self.0.len() - 1) as u32)
+    }
+
+    pub fn get(&self, faction: Faction) -> Option<&FactionDef> {
+        self.0.get(faction.0 as usize)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item=Faction> {
+        (0..self.0.len() as u32).map(Faction)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Relation {
+    Hostile,
+    Neutral,
+    Friendly,
+}
+
+impl Relation {
+    pub fn from_standing(standing: f32) -> Self {
+        if standing <= HOSTILE_THRESHOLD {
+            Relation::Hostile
+        } else if standing >= FRIENDLY_THRESHOLD {
+            Relation::Friendly
+        } else {
+            Relation::Neutral
+        }
+    }
+}
+
+/// Diplomacy matrix, how a faction sees other factions and individual pilots.
+/// Standings are one-directional : A liking B does not mean B likes A.
+/// Pilots are keyed by their `u_id` so standings survive entity churn.
+#[derive(Resource, Default)]
+pub struct Standings {
+    factions: HashMap<(Faction, Faction), f32>,
+    pilots: HashMap<(Faction, u64), f32>,
+}
+
+impl Standings {
+    pub fn faction(&self, from: Faction, to: Faction) -> f32 {
+        if from == to {
+            return STANDING_MAX;
+        }
+        *self.factions.get(&(from, to)).unwrap_or(&0.0)
+    }
+
+    pub fn set_faction(&mut self, from: Faction, to: Faction, value: f32) {
+        self.factions.insert((from, to), value.clamp(STANDING_MIN, STANDING_MAX));
+    }
+
+    /// Personal standing of a pilot if it has one, otherwise the standing toward its faction
+    pub fn pilot(&self, from: Faction, pilot: &Pilot, pilot_faction: Faction) -> f32 {
+        match self.pilots.get(&(from, pilot.u_id)) {
+            Some(value) => *value,
+            None => self.faction(from, pilot_faction),
+        }
+    }
+
+    pub fn faction_entries(&self) -> impl Iterator<Item=(Faction, Faction, f32)> + '_ {
+        self.factions.iter().map(|((from, to), v)| (*from, *to, *v))
+    }
+
+    pub fn pilot_entries(&self) -> impl Iterator<Item=(Faction, u64, f32)> + '_ {
+        self.pilots.iter().map(|((from, uid), v)| (*from, *uid, *v))
+    }
+
+    pub fn set_pilot(&mut self, from: Faction, pilot_uid: u64, value: f32) {
+        self.pilots.insert((from, pilot_uid), value.clamp(STANDING_MIN, STANDING_MAX));
+    }
+
+    pub fn relation(&self, from: Faction, pilot: &Pilot, pilot_faction: Faction) -> Relation {
+        Relation::from_standing(self.pilot(from, pilot, pilot_faction))
+    }
+
+    pub fn is_hostile(&self, from: Faction, pilot: &Pilot, pilot_faction: Faction) -> bool {
+        self.relation(from, pilot, pilot_faction) == Relation::Hostile
+    }
+
+    /// Stations refuse docking to pilots their owner is hostile to
+    pub fn can_dock(&self, station_owner: Faction, pilot: &Pilot, pilot_faction: Faction) -> bool {
+        !self.is_hostile(station_owner, pilot, pilot_faction)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StandingReason {
+    Kill,
+}
+
+/// Change the standing of `faction` toward the pilot `pilot_uid` and
+/// seed it from its faction standing if it had none
+pub struct StandingChangeEvent {
+    pub faction: Faction,
+    pub pilot_uid: u64,
+    pub pilot_faction: Faction,
+    pub reason: StandingReason,
+    pub amount: f32,
+}
+
+fn apply_standing_changes(mut standings: ResMut<Standings>,
+                          mut events: EventReader<StandingChangeEvent>) {
+    for ev in events.iter() {
+        let current = match standings.pilots.get(&(ev.faction, ev.pilot_uid)) {
+            Some(value) => *value,
+            None => standings.faction(ev.faction, ev.pilot_faction),
+        };
+        standings.set_pilot(ev.faction, ev.pilot_uid, current + ev.amount);
+    }
+}
+
+fn standings_from_activity(registry: Res<FactionRegistry>,
+                           standings: Res<Standings>,
+                           pilots: Query<(&Pilot, &Faction)>,
+                           mut kills: EventReader<PilotKilledEvent>,
+                           mut changes: EventWriter<StandingChangeEvent>) {
+    for ev in kills.iter() {
+        if let (Ok((_, victim_faction)), Ok((killer, killer_faction))) = (pilots.get(ev.victim), pilots.get(ev.killer)) {
+            changes.send(StandingChangeEvent {
+                faction: *victim_faction,
+                pilot_uid: killer.u_id,
+                pilot_faction: *killer_faction,
+                reason: StandingReason::Kill,
+                amount: -KILL_PENALTY,
+            });
+            //enemies of the victim are grateful
+            for other in registry.ids() {
+                if other != *victim_faction
+                    && Relation::from_standing(standings.faction(other, *victim_faction)) == Relation::Hostile {
+                    changes.send(StandingChangeEvent {
+                        faction: other,
+                        pilot_uid: killer.u_id,
+                        pilot_faction: *killer_faction,
+                        reason: StandingReason::Kill,
+                        amount: KILL_REWARD,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// NPC aggression, every pilot engages the closest hostile in its system
+/// and drops its lock once the target is gone, out of range or not hostile anymore.
+/// Police are left out, they pick their own targets
+fn npc_acquire_targets(mut commands: Commands,
+                       mut orders: EventWriter<OrderEvent>,
+                       scheduler: Res<Scheduler>,
+                       scale: Res<GalaxyScale>,
+                       standings: Res<Standings>,
+                       query: Query<(Entity, &Pilot, &Faction, &SimPosition, &GalaxyCoordinate, Option<&TargetLock>), Without<Police>>) {
+    let Some(slot) = scheduler.slot(NPC_TARGETING_JOB) else { return; };
+
+    let range = scale.to_sim(AGGRESSION_RANGE).0;
+    for (entity, pilot, faction, pos, coord, lock) in query.iter() {
+        if !slot.includes(pilot.u_id) {
+            continue;
+        }
+        if let Some(lock) = lock {
+            let keep = match query.get(lock.0) {
+                Ok((_, t_pilot, t_faction, t_pos, t_coord, _)) => {
+                    t_coord.0 == coord.0
+                        && t_pos.0.distance(pos.0) <= range
+                        && standings.is_hostile(*faction, t_pilot, *t_faction)
+                }
+                Err(_) => false,
+            };
+            if !keep {
+                commands.entity(entity).remove::<TargetLock>().remove::<Engaging>();
+            }
+            continue;
+        }
+
+        let mut closest: Option<(Entity, f64)> = None;
+        for (other, o_pilot, o_faction, o_pos, o_coord, _) in query.iter() {
+            if other == entity || o_coord.0 != coord.0 {
+                continue;
+            }
+            let dist = o_pos.0.distance(pos.0);
+            if dist <= range
+                && standings.is_hostile(*faction, o_pilot, *o_faction)
+                && closest.is_none_or(|(_, best)| dist < best) {
+                closest = Some((other, dist));
+            }
+        }
+
+        if let Some((target, _)) = closest {
+            orders.send(OrderEvent { ship: entity, order: ShipOrder::Fire(target), source: OrderSource::Ai });
+        }
+    }
+}
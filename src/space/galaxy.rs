@@ -1,6 +1,14 @@
-use bevy::{ecs::{entity::Entities, query}, prelude::*};
+use std::ops::AddAssign;
+
+use bevy::prelude::*;
 use bevy::math::DVec3;
-use bevy_mod_picking::{DefaultPickingPlugins, PickableBundle, PickingCameraBundle, PickingEvent};
+use bevy::sprite::MaterialMesh2dBundle;
+use bevy_mod_picking::{PickableBundle, PickingEvent, Selection};
+
+use crate::base::actions::{Action, ActionState};
+use crate::base::units::SimVec;
+use crate::space::security::SecurityStatus;
+use crate::space::ship::UndockLoc;
 
 /// Since we need every ship to be able to live in a different system/map
 /// we need to simulate them independently of the rendering, all in local space
@@ -13,8 +21,7 @@ pub struct SystemMap(pub Vec<Entity>);
 #[derive(Component, Deref)]
 pub struct GalaxyCoordinate(pub Entity);
 
-#[derive(Resource, Deref)]
-pub struct GalaxyScale(pub f64);
+pub use crate::base::units::GalaxyScale;
 
 /// Since coordinates are float we need to avoid going into large coordinates value
 #[derive(Component)]
@@ -24,10 +31,55 @@ pub struct SolarSystem {
     //pub size: f32, //probably useless we'll see
 }
 
-/// Position for simulation
+/// Empty solar system at `at` on the galaxy map, its quad is added by the galaxy view
+pub fn spawn_solar_system(commands: &mut Commands,
+                          at: DVec3,
+                          security: SecurityStatus) -> Entity {
+    commands.spawn(
+        (
+            SolarSystem {
+                anomalies: Vec::new(),
+                gates: Vec::new(),
+            },
+            UndockLoc,
+            security,
+            SimPosition(at),
+        )).id()
+}
+
+/// Clickable quad on the galaxy map for every new solar system
+pub fn attach_solar_system_display(mut commands: Commands,
+                                   mut meshes: ResMut<Assets<Mesh>>,
+                                   mut materials: ResMut<Assets<ColorMaterial>>,
+                                   query: Query<(Entity, &SimPosition), Added<SolarSystem>>) {
+    for (entity, pos) in query.iter() {
+        commands.entity(entity).insert((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+                material: materials.add(ColorMaterial::from(Color::RED)),
+                transform: Transform {
+                    translation: pos.0.as_vec3(),
+                    scale: Vec3 { x: 64.0, y: 64.0, z: 1.0 },
+                    ..default()
+                },
+                visibility: Visibility { is_visible: true },
+                ..default()
+            },
+            PickableBundle::default(),
+        )).remove::<Selection>();
+    }
+}
+
+/// Position for simulation, in sim units
 #[derive(Component, Default, Copy, Clone, Deref, DerefMut, Reflect)]
 pub struct SimPosition(pub DVec3);
 
+impl AddAssign<SimVec> for SimPosition {
+    fn add_assign(&mut self, rhs: SimVec) {
+        self.0 += rhs.0;
+    }
+}
+
 #[derive(Bundle)]
 pub struct GalaxyGateBundle {
     pub tag: GalaxyGateTag,
@@ -82,26 +134,23 @@ pub struct HideGalaxyEvent;
 pub struct RenderGalaxyEvent;
 
 pub fn exit_system_view(
-    keys: Res<Input<KeyCode>>,
+    actions: Res<ActionState>,
     mut ev: EventWriter<RenderGalaxyEvent>,
     mut ev_hide: EventWriter<HideSystemEvent>,
     mut state: ResMut<State<ViewState>>) {
 
     //println!("state {:?}",state.current());
-    if *state.current() != ViewState::GALAXY {
-        if keys.any_just_pressed([KeyCode::Numpad0, KeyCode::Escape]) {
-            ev_hide.send(HideSystemEvent);
-            ev.send(RenderGalaxyEvent);
-            state.set(ViewState::GALAXY);
-
-        }
+    if *state.current() != ViewState::GALAXY && actions.just_pressed(Action::ExitSystemView) {
+        ev_hide.send(HideSystemEvent);
+        ev.send(RenderGalaxyEvent);
+        let _ = state.set(ViewState::GALAXY);
     }
 }
 
 
 pub fn click_enter_system_view(
     mut events: EventReader<PickingEvent>,
-    query_clicked: Query<(Entity), (With<SolarSystem>)>,
+    query_clicked: Query<Entity, With<SolarSystem>>,
     mut ev: EventWriter<RenderSystemEvent>,
     mut ev_hide: EventWriter<HideGalaxyEvent>,
     mut state: ResMut<State<ViewState>>) {
@@ -113,7 +162,7 @@ pub fn click_enter_system_view(
                 if let Ok(isok) = query_clicked.get(*e) {
                     ev_hide.send(HideGalaxyEvent);
                     ev.send(RenderSystemEvent(isok));
-                    state.set(ViewState::SYSTEM);
+                    let _ = state.set(ViewState::SYSTEM);
                 }
             }
         }
@@ -125,33 +174,30 @@ pub struct RenderSystemEvent(Entity);
 
 pub fn flag_render_solar_system(mut commands: Commands,
                                 query_future: Query<(Entity, &GalaxyCoordinate), Without<Rendered>>,
-                                mut ev_render: EventReader<RenderSystemEvent>,
-                                mut state: ResMut<State<ViewState>>) {
+                                mut ev_render: EventReader<RenderSystemEvent>) {
     if !ev_render.is_empty() {
         let sys = ev_render.iter().next();
-        match sys {
-            Some(val) => {
-                //flag for render entites in system val
-                for (entity, galaxy) in &query_future {
-                    if galaxy.0 == val.0 {
-                        commands.entity(entity).insert(RenderFlag);
-                    }
+        if let Some(val) = sys {
+            //flag for render entites in system val
+            for (entity, galaxy) in &query_future {
+                if galaxy.0 == val.0 {
+                    commands.entity(entity).insert(RenderFlag);
                 }
-                println!("render map {:?}", val.0);
             }
-            None => {}
+            println!("render map {:?}", val.0);
         }
     }
 }
 
 pub fn hide_system_view(mut commands: Commands,
-                        mut query: Query<(Entity, &mut Visibility), (With<Rendered>)>,
-                        mut state: ResMut<State<ViewState>>,
-                        mut ev_hide: EventReader<HideSystemEvent>) {
+                        mut query: Query<(Entity, Option<&mut Visibility>), With<Rendered>>,
+                        ev_hide: EventReader<HideSystemEvent>) {
     if !ev_hide.is_empty() {
         //let sys = ev_hide.iter().next();
-        for (entity, mut vis) in &mut query {
-            vis.is_visible = false;
+        for (entity, vis) in &mut query {
+            if let Some(mut vis) = vis {
+                vis.is_visible = false;
+            }
             commands.entity(entity).remove::<Rendered>();
         }
         //state.set(ViewState::EMPTY);
@@ -159,9 +205,8 @@ pub fn hide_system_view(mut commands: Commands,
 }
 
 pub fn hide_galaxy_view(mut commands: Commands,
-                        mut query: Query<(Entity, &mut Visibility), (With<SolarSystem>)>,
-                        mut state: ResMut<State<ViewState>>,
-                        mut ev_hide: EventReader<HideGalaxyEvent>) {
+                        mut query: Query<(Entity, &mut Visibility), With<SolarSystem>>,
+                        ev_hide: EventReader<HideGalaxyEvent>) {
     if !ev_hide.is_empty() {
         //let sys = ev_hide.iter().next();
         for (entity, mut vis) in &mut query {
@@ -173,8 +218,7 @@ pub fn hide_galaxy_view(mut commands: Commands,
 }
 
 pub fn generate_galaxy_view(mut commands: Commands,
-                            mut query_clicked: Query<(Entity, &mut Visibility), (With<SolarSystem>)>,
-                            mut state: ResMut<State<ViewState>>,
+                            mut query_clicked: Query<(Entity, &mut Visibility), With<SolarSystem>>,
                             ev_render: EventReader<RenderGalaxyEvent>) {
     if !ev_render.is_empty() {
         for (entity, mut vis) in &mut query_clicked {
@@ -186,11 +230,10 @@ pub fn generate_galaxy_view(mut commands: Commands,
     }
 }
 
+/// Visuals are attached by the presentation layer once `Rendered` is there
 pub fn generate_system_view(mut commands: Commands,
-                            mut query: Query<(Entity, &mut Visibility), Added<RenderFlag>>,
-                            mut state: ResMut<State<ViewState>>) {
-    for (entity, mut vis) in &mut query {
-        vis.is_visible = true;
+                            query: Query<Entity, Added<RenderFlag>>) {
+    for entity in &query {
         commands.entity(entity).insert(Rendered).remove::<RenderFlag>();
     }
 }
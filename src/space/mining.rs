@@ -0,0 +1,79 @@
+//! Ore fields sit next to stations. Every [`MINING_CYCLE`], ships idling within [`MINING_RANGE`]
+//! of a field mine [`ORE_PER_CYCLE`] scaled by their mining skills into their [`Cargo`], and send an
+//! [`OreMinedEvent`]. Fields never run out.
+
+use std::time::Duration;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::base::scheduler::{Job, JobClock, job_due, Scheduler};
+use crate::base::units::{GalaxyScale, Metres};
+use crate::base::velocity::Velocity;
+use crate::space::activity::OreMinedEvent;
+use crate::space::cargo::{Cargo, Item};
+use crate::space::combat::Engaging;
+use crate::space::galaxy::{AnomalyMining, GalaxyCoordinate, SimPosition};
+use crate::space::orders::{OrderSystem, Warping};
+use crate::space::ship::HullClass;
+use crate::space::skills::SkillBonuses;
+
+/// Ships this close to a field can mine it
+pub const MINING_RANGE: Metres = Metres(10_000.0);
+/// Distance between a station and its field
+const FIELD_OFFSET: Metres = Metres(5_000.0);
+/// Faster ships are passing by, in m/s
+const MINING_MAX_SPEED: f64 = 5.0;
+/// Volume mined by an unskilled pilot each cycle, in m3
+const ORE_PER_CYCLE: f64 = 50.0;
+const MINING_CYCLE: Duration = Duration::from_secs(10);
+const MINING_JOB: &str = "mining";
+
+pub struct MiningPlugin;
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum MiningSystem {
+    Mine,
+}
+
+impl Plugin for MiningPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(mine_fields
+            .with_run_criteria(job_due(MINING_JOB))
+            .label(MiningSystem::Mine)
+            .after(OrderSystem::Warp));
+        app.world.get_resource_or_insert_with(Scheduler::default)
+            .add(MINING_JOB, Job::every(JobClock::Sim, MINING_CYCLE));
+    }
+}
+
+/// Where the field of a station at `station` goes
+pub fn field_position(station: DVec3, scale: &GalaxyScale) -> DVec3 {
+    station + DVec3::X * scale.to_sim(FIELD_OFFSET).0
+}
+
+/// Mining anomaly, add it to the [`SolarSystem`](crate::space::galaxy::SolarSystem) anomalies
+pub fn ore_field(system: Entity, at: DVec3) -> (AnomalyMining, SimPosition, GalaxyCoordinate) {
+    (AnomalyMining, SimPosition(at), GalaxyCoordinate(system))
+}
+
+fn mine_fields(scale: Res<GalaxyScale>,
+               fields: Query<(&SimPosition, &GalaxyCoordinate), With<AnomalyMining>>,
+               mut ships: Query<(Entity, &SimPosition, &GalaxyCoordinate, &Velocity, Option<&SkillBonuses>, Option<&mut Cargo>),
+                   (With<HullClass>, Without<Engaging>, Without<Warping>)>,
+               mut mined: EventWriter<OreMinedEvent>) {
+    let range = scale.to_sim(MINING_RANGE).0;
+    for (entity, pos, coord, velocity, bonuses, cargo) in ships.iter_mut() {
+        if velocity.0.length() > MINING_MAX_SPEED {
+            continue;
+        }
+        let in_range = fields.iter().any(|(f_pos, f_coord)| f_coord.0 == coord.0 && f_pos.0.distance(pos.0) <= range);
+        if in_range {
+            let volume = ORE_PER_CYCLE * bonuses.map_or(1.0, |b| b.mining_yield as f64);
+            if let Some(mut cargo) = cargo {
+                cargo.add(Item::Ore, volume);
+            }
+            mined.send(OreMinedEvent { pilot: entity, volume });
+        }
+    }
+}
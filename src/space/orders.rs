@@ -0,0 +1,277 @@
+//! Everything a player can tell a ship to do goes through [`OrderEvent`],
+//! whether it comes from the local UI, a network client or a replay.
+
+use bevy::math::{DQuat, DVec3};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::base::timer::SimClock;
+use crate::base::units::{GalaxyScale, Metres};
+use crate::base::velocity::{PlaneLock, Velocity, VelocitySystem};
+use crate::space::combat::Engaging;
+use crate::space::galaxy::{GalaxyCoordinate, GateDestination, Rendered, SimPosition};
+use crate::space::security::AggressionEvent;
+use crate::space::ship::{Destination, DestoType, ShipBundle, TargetLock, UndockingFrom};
+use crate::space::station::Station;
+
+/// Ships dock once they are this close to the station
+const DOCKING_RANGE: Metres = Metres(2_500.0);
+/// Gates only take ships this close
+pub const JUMP_RANGE: Metres = Metres(2_500.0);
+/// Targets can't be locked from further away
+pub const LOCK_RANGE: Metres = Metres(150_000.0);
+/// Distance kept from an orbited entity
+pub const ORBIT_DISTANCE: Metres = Metres(5_000.0);
+/// How far along the orbit the destination is kept, in radians
+const ORBIT_LEAD: f64 = 0.5;
+/// Closer points are flown to, not warped to
+pub const WARP_MIN_RANGE: Metres = Metres(150_000.0);
+/// Distance crossed each second in warp, about 20 AU
+const WARP_SPEED: Metres = Metres(3.0e12);
+
+pub struct OrdersPlugin;
+
+impl Plugin for OrdersPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<OrderEvent>()
+            .add_system(apply_orders.label(OrderSystem::Apply))
+            .add_system(follow_targets.label(OrderSystem::Follow).after(OrderSystem::Apply))
+            //warps jump over the regular movement of the frame
+            .add_system(warp_ships.label(OrderSystem::Warp).after(VelocitySystem::Apply))
+            .add_system(complete_docking.after(OrderSystem::Warp));
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum OrderSystem {
+    Apply,
+    Follow,
+    Warp,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShipOrder {
+    /// Fly to a point of the ship's system, in sim units
+    Navigate([f64; 3]),
+    /// Fly to a station and dock
+    Dock(Entity),
+    /// Fly to an entity and keep following it
+    Approach(Entity),
+    /// Circle an entity at [`ORBIT_DISTANCE`]
+    Orbit(Entity),
+    /// Lock a target without engaging it
+    Lock(Entity),
+    /// Lock and engage a target
+    Fire(Entity),
+    /// Cross the system in warp to a point at least [`WARP_MIN_RANGE`] away, in sim units
+    Warp([f64; 3]),
+    /// Go through a gate within [`JUMP_RANGE`], out at the gate it leads to
+    Jump(Entity),
+    /// Kill the engines and drop any target
+    Stop,
+    /// Leave the station the pilot is docked in
+    Undock,
+}
+
+/// Who gave an order, a replay only feeds back player orders since the AI gives its own again
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum OrderSource {
+    Player,
+    Ai,
+}
+
+pub struct OrderEvent {
+    pub ship: Entity,
+    pub order: ShipOrder,
+    pub source: OrderSource,
+}
+
+/// Flying to a station to dock
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct DockingAt(pub Entity);
+
+/// Destination kept relative to another entity of the system
+#[derive(Component, Debug, Copy, Clone)]
+#[component(storage = "SparseSet")]
+pub enum Following {
+    Approach(Entity),
+    Orbit(Entity),
+}
+
+/// In warp toward `exit`, the ship ignores orders until it drops out
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Warping {
+    pub exit: DVec3,
+}
+
+/// Pilot sitting in a station, without ship
+#[derive(Component)]
+pub struct Docked(pub Entity);
+
+fn apply_orders(mut commands: Commands,
+                scale: Res<GalaxyScale>,
+                mut orders: EventReader<OrderEvent>,
+                mut ships: Query<(&SimPosition, &GalaxyCoordinate, &mut Destination)>,
+                docked: Query<&Docked>,
+                warping: Query<(), With<Warping>>,
+                targets: Query<(&SimPosition, &GalaxyCoordinate)>,
+                stations: Query<(), With<Station>>,
+                gates: Query<&GateDestination>,
+                mut aggression: EventWriter<AggressionEvent>) {
+    for ev in orders.iter() {
+        if ev.order == ShipOrder::Undock {
+            if let Ok(docked) = docked.get(ev.ship) {
+                commands.entity(ev.ship).insert(UndockingFrom(docked.0));
+            }
+            continue;
+        }
+        if warping.contains(ev.ship) {
+            continue;
+        }
+        let Ok((ship_pos, coord, mut dest)) = ships.get_mut(ev.ship) else { continue; };
+        //anything targeted has to be in the same system
+        let local = |target: Entity| targets.get(target).ok().filter(|(_, c)| c.0 == coord.0).map(|(p, _)| *p);
+        let within = |target: Entity, range: Metres| local(target)
+            .is_some_and(|p| p.0.distance(ship_pos.0) <= scale.to_sim(range).0);
+
+        match ev.order {
+            ShipOrder::Navigate(at) => {
+                dest.0 = DestoType::DPosition(SimPosition(DVec3::from_array(at)));
+                commands.entity(ev.ship).remove::<DockingAt>().remove::<Following>();
+            }
+            ShipOrder::Dock(station) => {
+                let Some(pos) = local(station).filter(|_| stations.contains(station)) else { continue; };
+                dest.0 = DestoType::TEntity(pos);
+                commands.entity(ev.ship).insert(DockingAt(station)).remove::<Following>();
+            }
+            ShipOrder::Approach(target) | ShipOrder::Orbit(target) => {
+                if target == ev.ship || local(target).is_none() {
+                    continue;
+                }
+                let following = match ev.order {
+                    ShipOrder::Orbit(_) => Following::Orbit(target),
+                    _ => Following::Approach(target),
+                };
+                commands.entity(ev.ship).insert(following).remove::<DockingAt>();
+            }
+            ShipOrder::Lock(target) => {
+                if target == ev.ship || !within(target, LOCK_RANGE) {
+                    continue;
+                }
+                commands.entity(ev.ship).insert(TargetLock(target)).remove::<Engaging>();
+            }
+            ShipOrder::Fire(target) => {
+                if target == ev.ship || local(target).is_none() {
+                    continue;
+                }
+                commands.entity(ev.ship).insert((TargetLock(target), Engaging));
+                aggression.send(AggressionEvent { aggressor: ev.ship, target });
+            }
+            ShipOrder::Warp(at) => {
+                let exit = DVec3::from_array(at);
+                if exit.distance(ship_pos.0) < scale.to_sim(WARP_MIN_RANGE).0 {
+                    continue;
+                }
+                dest.0 = DestoType::None;
+                commands.entity(ev.ship)
+                    .insert(Warping { exit })
+                    .remove::<DockingAt>()
+                    .remove::<Following>();
+            }
+            ShipOrder::Jump(gate) => {
+                let Ok(exit) = gates.get(gate) else { continue; };
+                let Ok((exit_pos, exit_coord)) = targets.get(exit.0) else { continue; };
+                if !within(gate, JUMP_RANGE) {
+                    continue;
+                }
+                dest.0 = DestoType::None;
+                //the view only shows the system it was opened on
+                commands.entity(ev.ship)
+                    .insert((*exit_pos, GalaxyCoordinate(exit_coord.0), Velocity::default()))
+                    .remove::<TargetLock>()
+                    .remove::<Engaging>()
+                    .remove::<DockingAt>()
+                    .remove::<Following>()
+                    .remove::<Rendered>();
+            }
+            ShipOrder::Stop => {
+                dest.0 = DestoType::None;
+                commands.entity(ev.ship).remove::<TargetLock>().remove::<Engaging>().remove::<DockingAt>().remove::<Following>();
+            }
+            ShipOrder::Undock => {}
+        }
+    }
+}
+
+/// Ships close enough to their station leave space, the pilot stays in the station
+fn complete_docking(mut commands: Commands,
+                    scale: Res<GalaxyScale>,
+                    ships: Query<(Entity, &SimPosition, &DockingAt)>,
+                    stations: Query<&SimPosition, With<Station>>) {
+    let range = scale.to_sim(DOCKING_RANGE).0;
+    for (entity, pos, docking) in ships.iter() {
+        let Ok(station) = stations.get(docking.0) else {
+            commands.entity(entity).remove::<DockingAt>();
+            continue;
+        };
+        if station.0.distance(pos.0) <= range {
+            commands.entity(entity)
+                .remove::<ShipBundle>()
+                .remove::<TargetLock>()
+                .remove::<Engaging>()
+                .remove::<DockingAt>()
+                .remove::<Following>()
+                .remove::<Warping>()
+                .remove::<PlaneLock>()
+                //its sprite goes with the ship, undocking draws a new one
+                .remove::<Rendered>()
+                .insert(Docked(docking.0));
+        }
+    }
+}
+
+/// Keep the destination of following ships on their target, they stop once it is gone
+fn follow_targets(mut commands: Commands,
+                  scale: Res<GalaxyScale>,
+                  mut ships: Query<(Entity, &SimPosition, &GalaxyCoordinate, &Following, &mut Destination)>,
+                  targets: Query<(&SimPosition, &GalaxyCoordinate)>) {
+    let radius = scale.to_sim(ORBIT_DISTANCE).0;
+    for (entity, pos, coord, following, mut dest) in ships.iter_mut() {
+        let (Following::Approach(target) | Following::Orbit(target)) = *following;
+        let Some((target_pos, _)) = targets.get(target).ok().filter(|(_, c)| c.0 == coord.0) else {
+            dest.0 = DestoType::None;
+            commands.entity(entity).remove::<Following>();
+            continue;
+        };
+        dest.0 = match following {
+            Following::Approach(_) => DestoType::TEntity(*target_pos),
+            Following::Orbit(_) => {
+                //aim a bit further along the circle, counterclockwise around z, at the current elevation
+                let direction = (pos.0 - target_pos.0).try_normalize().unwrap_or(DVec3::X);
+                let at = target_pos.0 + DQuat::from_rotation_z(ORBIT_LEAD) * direction * radius;
+                DestoType::DPosition(SimPosition(at))
+            }
+        };
+    }
+}
+
+/// Move warping ships straight to their exit, engines off, and drop them out there at rest
+fn warp_ships(mut commands: Commands,
+              clock: Res<SimClock>,
+              scale: Res<GalaxyScale>,
+              mut ships: Query<(Entity, &mut SimPosition, &mut Velocity, &Warping)>) {
+    let step = scale.to_sim(WARP_SPEED).0 * clock.delta_seconds_f64();
+    for (entity, mut pos, mut velocity, warp) in ships.iter_mut() {
+        velocity.0 = DVec3::ZERO;
+        let left = warp.exit - pos.0;
+        if left.length() <= step {
+            pos.0 = warp.exit;
+            commands.entity(entity).remove::<Warping>();
+        } else {
+            pos.0 += left.normalize() * step;
+        }
+    }
+}
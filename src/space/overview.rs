@@ -0,0 +1,282 @@
+//! Overview of the viewed solar system : a sortable list of what is in it, filtered by
+//! presets from the settings file. Clicking a row selects the entity like picking does.
+
+use std::cmp::Ordering;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use bevy_mod_picking::Selection;
+use serde::{Deserialize, Serialize};
+
+use crate::base::actions::{Action, ActionState};
+use crate::base::settings::GameplaySettings;
+use crate::base::units::{GalaxyScale, Metres, SimUnits};
+use crate::base::velocity::Velocity;
+use crate::space::faction::{Relation, Standings};
+use crate::space::galaxy::{Rendered, SimPosition, SolarSystem, ViewState};
+use crate::space::pilot::{EName, Faction, Pilot};
+use crate::space::security::Police;
+use crate::space::ship::HullClass;
+use crate::space::station::Station;
+
+pub struct OverviewPlugin;
+
+impl Plugin for OverviewPlugin {
+    fn build(&self, app: &mut App) {
+        crate::base::ensure_egui(app);
+        app
+            .init_resource::<OverviewState>()
+            .add_system(overview_window);
+    }
+}
+
+/// Which entities an overview preset lists, edited in the settings file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverviewPreset {
+    pub name: String,
+    pub ships: bool,
+    pub stations: bool,
+    /// Only what is hostile to the selected ship
+    pub hostile_only: bool,
+}
+
+impl OverviewPreset {
+    pub fn defaults() -> Vec<OverviewPreset> {
+        let preset = |name: &str, ships, stations, hostile_only| OverviewPreset {
+            name: name.to_string(),
+            ships,
+            stations,
+            hostile_only,
+        };
+        vec![
+            preset("all", true, true, false),
+            preset("ships", true, false, false),
+            preset("stations", false, true, false),
+            preset("hostiles", true, true, true),
+        ]
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OverviewColumn {
+    Name,
+    Type,
+    Distance,
+    Velocity,
+}
+
+impl OverviewColumn {
+    const ALL: [OverviewColumn; 4] = [OverviewColumn::Name, OverviewColumn::Type, OverviewColumn::Distance, OverviewColumn::Velocity];
+
+    fn title(&self) -> &'static str {
+        match self {
+            OverviewColumn::Name => "Name",
+            OverviewColumn::Type => "Type",
+            OverviewColumn::Distance => "Distance",
+            OverviewColumn::Velocity => "Velocity",
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct OverviewState {
+    /// Index into the presets of [`GameplaySettings`]
+    pub preset: usize,
+    pub sort: OverviewColumn,
+    pub descending: bool,
+}
+
+impl Default for OverviewState {
+    fn default() -> Self {
+        Self { preset: 0, sort: OverviewColumn::Distance, descending: false }
+    }
+}
+
+struct OverviewRow {
+    entity: Entity,
+    name: String,
+    kind: String,
+    distance: Option<Metres>,
+    /// m/s
+    speed: Option<f64>,
+    selected: bool,
+}
+
+impl OverviewRow {
+    fn cmp(&self, other: &Self, column: OverviewColumn) -> Ordering {
+        //entities without a value go last
+        let by = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        match column {
+            OverviewColumn::Name => self.name.cmp(&other.name),
+            OverviewColumn::Type => self.kind.cmp(&other.kind),
+            OverviewColumn::Distance => by(self.distance.map(|d| d.0), other.distance.map(|d| d.0)),
+            OverviewColumn::Velocity => by(self.speed, other.speed),
+        }
+    }
+}
+
+type ShownEntity = (
+    Entity,
+    &'static SimPosition,
+    Option<&'static EName>,
+    Option<&'static HullClass>,
+    Option<&'static Station>,
+    Option<&'static Police>,
+    Option<&'static Velocity>,
+    Option<&'static Pilot>,
+    Option<&'static Faction>,
+);
+
+#[derive(SystemParam)]
+pub struct OverviewQuery<'w, 's> {
+    scale: Res<'w, GalaxyScale>,
+    standings: Res<'w, Standings>,
+    shown: Query<'w, 's, ShownEntity, (With<Rendered>, Without<SolarSystem>)>,
+    selections: Query<'w, 's, &'static mut Selection>,
+}
+
+impl<'w, 's> OverviewQuery<'w, 's> {
+    /// First selected ship, distances and hostility are seen from it
+    fn reference(&self) -> Option<(SimPosition, Option<Faction>)> {
+        self.shown.iter()
+            .filter(|(e, _, _, hull, ..)| hull.is_some() && self.is_selected(*e))
+            .map(|(_, pos, .., faction)| (*pos, faction.copied()))
+            .next()
+    }
+
+    fn is_selected(&self, entity: Entity) -> bool {
+        self.selections.get(entity).is_ok_and(|s| s.selected())
+    }
+
+    fn rows(&self, preset: &OverviewPreset) -> Vec<OverviewRow> {
+        let reference = self.reference();
+        let viewer = reference.and_then(|(_, f)| f);
+        self.shown.iter()
+            .filter(|(_, _, _, hull, station, ..)| (preset.ships && hull.is_some()) || (preset.stations && station.is_some()))
+            .filter(|(_, _, _, _, station, _, _, pilot, faction)| {
+                if !preset.hostile_only {
+                    return true;
+                }
+                let (Some(viewer), Some(faction)) = (viewer, faction) else { return false; };
+                match (pilot, station) {
+                    (Some(pilot), _) => self.standings.is_hostile(viewer, pilot, **faction),
+                    (None, Some(_)) => Relation::from_standing(self.standings.faction(**faction, viewer)) == Relation::Hostile,
+                    _ => false,
+                }
+            })
+            .map(|(entity, pos, name, hull, station, police, velocity, ..)| {
+                let kind = match (hull, station, police) {
+                    (_, Some(_), _) => String::from("station"),
+                    (Some(hull), _, Some(_)) => format!("police {:?}", hull).to_lowercase(),
+                    (Some(hull), _, None) => format!("{:?}", hull).to_lowercase(),
+                    _ => String::from("?"),
+                };
+                OverviewRow {
+                    entity,
+                    name: name.map_or_else(|| format!("{:?}", entity), |n| n.0.clone()),
+                    kind,
+                    distance: reference.map(|(from, _)| self.scale.to_metres(SimUnits(from.0.distance(pos.0)))),
+                    speed: velocity.map(|v| v.0.length()),
+                    selected: self.is_selected(entity),
+                }
+            })
+            .collect()
+    }
+
+    /// Select `entity` alone, or toggle it when `add` is set
+    fn select(&mut self, entity: Entity, add: bool) {
+        if add {
+            if let Ok(mut selection) = self.selections.get_mut(entity) {
+                let selected = selection.selected();
+                selection.set_selected(!selected);
+            }
+            return;
+        }
+        for (other, ..) in self.shown.iter() {
+            if let Ok(mut selection) = self.selections.get_mut(other) {
+                if selection.selected() != (other == entity) {
+                    selection.set_selected(other == entity);
+                }
+            }
+        }
+    }
+}
+
+pub fn format_distance(d: Metres) -> String {
+    if d.0 < 10_000.0 {
+        format!("{:.0} m", d.0)
+    } else {
+        format!("{:.0} km", d.0 / 1000.0)
+    }
+}
+
+fn overview_window(mut egui: ResMut<EguiContext>,
+                   view: Res<State<ViewState>>,
+                   settings: Res<GameplaySettings>,
+                   actions: Res<ActionState>,
+                   mut state: ResMut<OverviewState>,
+                   mut overview: OverviewQuery) {
+    if *view.current() != ViewState::SYSTEM {
+        return;
+    }
+    let presets = &settings.overview_presets;
+    let Some(preset) = presets.get(state.preset).or_else(|| presets.first()) else { return; };
+    let mut rows = overview.rows(preset);
+    let column = state.sort;
+    rows.sort_by(|a, b| a.cmp(b, column));
+    if state.descending {
+        rows.reverse();
+    }
+
+    let mut clicked = None;
+    egui::Window::new("Overview")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8.0, 32.0))
+        .default_width(360.0)
+        .show(egui.ctx_mut(), |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for (i, preset) in presets.iter().enumerate() {
+                    ui.selectable_value(&mut state.preset, i, &preset.name);
+                }
+            });
+            ui.separator();
+            egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                egui::Grid::new("overview_rows").striped(true).num_columns(4).show(ui, |ui| {
+                    for column in OverviewColumn::ALL {
+                        let arrow = match (state.sort == column, state.descending) {
+                            (true, false) => " ^",
+                            (true, true) => " v",
+                            (false, _) => "",
+                        };
+                        if ui.selectable_label(state.sort == column, format!("{}{}", column.title(), arrow)).clicked() {
+                            state.descending = state.sort == column && !state.descending;
+                            state.sort = column;
+                        }
+                    }
+                    ui.end_row();
+
+                    for row in rows.iter() {
+                        if ui.selectable_label(row.selected, &row.name).clicked() {
+                            clicked = Some(row.entity);
+                        }
+                        ui.label(&row.kind);
+                        ui.label(row.distance.map_or(String::from("-"), format_distance));
+                        ui.label(row.speed.map_or(String::from("-"), |s| format!("{:.0} m/s", s)));
+                        ui.end_row();
+                    }
+                });
+            });
+            if preset.hostile_only && overview.reference().is_none() {
+                ui.weak("select a ship to see what is hostile to it");
+            }
+        });
+
+    if let Some(entity) = clicked {
+        overview.select(entity, actions.pressed(Action::AddToSelection));
+    }
+}
@@ -1,17 +1,53 @@
-use bevy::{ecs::component, prelude::*, transform::components};
+use std::collections::HashMap;
 
-use crate::base::velocity::*;
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
 
-pub fn spawn_new_pilot() -> PilotBundle {
-    return PilotBundle {
+use crate::base::rng::SimRng;
+use crate::space::cargo::Cargo;
+use crate::space::faction::FactionRegistry;
+use crate::space::skills::{PilotAttributes, SkillBonuses, Skills, TrainingQueue};
+
+/// Mixed into the run seed so names don't follow the simulation draws
+pub const DEFAULT_NAME_SEED: u64 = 0x5A1_00F;
+
+pub struct PilotPlugin;
+
+impl Plugin for PilotPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<SimRng>()
+            .insert_resource(PilotNameGenerator::new(DEFAULT_NAME_SEED))
+            .insert_resource(PilotIndex::default())
+            .add_startup_system_to_stage(StartupStage::PreStartup, seed_pilot_names)
+            .add_system_to_stage(CoreStage::PostUpdate, index_pilots);
+    }
+}
+
+/// Names follow the run seed, a load puts the saved one back
+fn seed_pilot_names(rng: Res<SimRng>, mut names: ResMut<PilotNameGenerator>) {
+    names.reseed(rng.seed() ^ DEFAULT_NAME_SEED);
+}
+
+pub fn spawn_new_pilot(faction: Faction, names: &mut PilotNameGenerator) -> PilotBundle {
+    let u_id = names.next_uid();
+    PilotBundle {
         _pilot: Pilot {
             level: 1,
-            u_id: 0,
+            experience: 0,
+            u_id,
         },
         respawn_base: RespawnBase(None),
-        pilot_name: EName("ZEZRRTERT".to_string()),
-        pilot_faction: Faction(0),
-    };
+        pilot_name: EName(names.generate(faction, u_id)),
+        pilot_faction: faction,
+        attributes: PilotAttributes::default(),
+        skills: Skills::default(),
+        training: TrainingQueue::default(),
+        bonuses: SkillBonuses::default(),
+        cargo: Cargo::default(),
+    }
 }
 
 
@@ -21,6 +57,11 @@ pub struct PilotBundle {
     pub respawn_base: RespawnBase,
     pub pilot_name: EName,
     pub pilot_faction: Faction,
+    pub attributes: PilotAttributes,
+    pub skills: Skills,
+    pub training: TrainingQueue,
+    pub bonuses: SkillBonuses,
+    pub cargo: Cargo,
 }
 
 #[derive(Component, Deref, DerefMut)]
@@ -29,12 +70,15 @@ pub struct RespawnBase(pub Option<Entity>);
 #[derive(Component, Deref, DerefMut)]
 pub struct EName(pub String);
 
-#[derive(Component, Deref, DerefMut)]
+/// Index into the [`FactionRegistry`](crate::space::faction::FactionRegistry)
+#[derive(Component, Deref, DerefMut, Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct Faction(pub u32);
 
 #[derive(Component)]
 pub struct Pilot {
     pub level: u8,
+    ///Total experience earned, see [`ProgressionSettings`](crate::space::progression::ProgressionSettings)
+    pub experience: u64,
     pub u_id: u64,
 }
 
@@ -43,3 +87,136 @@ pub struct PilotLevel(u8);
 
 #[derive(Component, Deref, DerefMut)]
 pub struct PilotUID(u64);
+
+
+/// Syllables a faction builds its pilot names from, kept on its
+/// [`FactionDef`](crate::space::faction::FactionDef) and saved with it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NameLexicon {
+    pub first_syllables: Vec<String>,
+    pub last_syllables: Vec<String>,
+    /// Syllables used to build surnames
+    pub family_syllables: Vec<String>,
+}
+
+impl NameLexicon {
+    pub fn new(first: &[&str], last: &[&str], family: &[&str]) -> Self {
+        let owned = |syllables: &[&str]| syllables.iter().map(|s| s.to_string()).collect();
+        Self {
+            first_syllables: owned(first),
+            last_syllables: owned(last),
+            family_syllables: owned(family),
+        }
+    }
+}
+
+impl Default for NameLexicon {
+    fn default() -> Self {
+        Self::new(
+            &["ka", "ri", "to", "mes", "an", "del", "vo", "sar"],
+            &["n", "ra", "ko", "lis", "th", "ne"],
+            &["vel", "dor", "ma", "ker", "ost", "in", "gar"],
+        )
+    }
+}
+
+/// Seeded pilot name and unique id source. A name only depends on the seed and the pilot's id,
+/// so the seed and the next id are all a save needs to keep naming the same way
+#[derive(Resource)]
+pub struct PilotNameGenerator {
+    seed: u64,
+    next_uid: u64,
+    lexicons: HashMap<Faction, NameLexicon>,
+    fallback: NameLexicon,
+}
+
+impl PilotNameGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            next_uid: 1,
+            lexicons: HashMap::new(),
+            fallback: NameLexicon::default(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Names handed out from now on follow `seed`, ids are not affected
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Take the lexicons of every registered faction, call again once factions change
+    pub fn use_lexicons(&mut self, registry: &FactionRegistry) {
+        self.lexicons = registry.ids()
+            .filter_map(|faction| registry.get(faction).map(|def| (faction, def.lexicon.clone())))
+            .collect();
+    }
+
+    /// Never hands out the same id twice, 0 is kept as "no pilot"
+    pub fn next_uid(&mut self) -> u64 {
+        let id = self.next_uid;
+        self.next_uid += 1;
+        id
+    }
+
+    /// First id that has not been handed out yet
+    pub fn peek_uid(&self) -> u64 {
+        self.next_uid
+    }
+
+    /// Used when restoring pilots with known ids so new ones don't collide
+    pub fn reserve_uid(&mut self, u_id: u64) {
+        self.next_uid = self.next_uid.max(u_id + 1);
+    }
+
+    pub fn generate(&self, faction: Faction, u_id: u64) -> String {
+        let lexicon = self.lexicons.get(&faction).unwrap_or(&self.fallback);
+        let rng = &mut StdRng::seed_from_u64(self.seed ^ u_id.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+
+        let mut first = String::new();
+        for _ in 0..rng.gen_range(1..=2) {
+            first.push_str(lexicon.first_syllables.choose(rng).map_or("", |s| s.as_str()));
+        }
+        first.push_str(lexicon.last_syllables.choose(rng).map_or("", |s| s.as_str()));
+
+        let mut family = String::new();
+        for _ in 0..rng.gen_range(2..=3) {
+            family.push_str(lexicon.family_syllables.choose(rng).map_or("", |s| s.as_str()));
+        }
+
+        format!("{} {}", capitalize(&first), capitalize(&family))
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Lookup of live pilots by their `u_id`
+#[derive(Resource, Default)]
+pub struct PilotIndex(pub HashMap<u64, Entity>);
+
+impl PilotIndex {
+    pub fn get(&self, u_id: u64) -> Option<Entity> {
+        self.0.get(&u_id).copied()
+    }
+}
+
+fn index_pilots(mut index: ResMut<PilotIndex>,
+                added: Query<(Entity, &Pilot), Added<Pilot>>,
+                removed: RemovedComponents<Pilot>) {
+    for entity in removed.iter() {
+        index.0.retain(|_, e| *e != entity);
+    }
+    for (entity, pilot) in added.iter() {
+        index.0.insert(pilot.u_id, entity);
+    }
+}
@@ -0,0 +1,127 @@
+//! Visuals of simulation entities, attached when they get `Rendered` and dropped when they lose it.
+//! Nothing in the simulation reads these components, selection included.
+
+use bevy::prelude::*;
+use bevy_mod_picking::Selection;
+
+use crate::space::galaxy::{Rendered, SolarSystem};
+use crate::space::project::Offscreen;
+use crate::space::security::Police;
+use crate::space::ship::HullClass;
+use crate::space::station::Station;
+
+const SHIP_COLOR: Color = Color::rgb(0.25, 0.25, 0.75);
+const POLICE_COLOR: Color = Color::WHITE;
+const STATION_COLOR: Color = Color::rgb(0.25, 0.85, 0.15);
+const STATION_SIZE: Vec2 = Vec2::new(24.0, 26.0);
+const POINTER_COLOR: Color = Color::rgb(0.95, 0.75, 0.2);
+const POINTER_SIZE: Vec2 = Vec2::new(12.0, 3.0);
+/// Distance between an off-screen entity's indicator and its pointer, in pixels
+const POINTER_DISTANCE: f32 = 16.0;
+
+pub struct PresentationPlugin;
+
+impl Plugin for PresentationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(attach_ship_sprites)
+            .add_system(attach_station_sprites)
+            .add_system(resize_ship_sprites)
+            .add_system(attach_edge_pointers)
+            .add_system(aim_edge_pointers)
+            .add_system_to_stage(CoreStage::PostUpdate, detach_edge_pointers)
+            .add_system_to_stage(CoreStage::PostUpdate, detach_sprites);
+    }
+}
+
+fn sprite(color: Color, size: Vec2) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(size),
+            ..default()
+        },
+        ..default()
+    }
+}
+
+fn attach_ship_sprites(mut commands: Commands,
+                       query: Query<(Entity, &HullClass, Option<&Police>), Added<Rendered>>) {
+    for (entity, hull, police) in query.iter() {
+        let color = if police.is_some() { POLICE_COLOR } else { SHIP_COLOR };
+        commands.entity(entity).insert((sprite(color, Vec2::splat(hull.sprite_size())), Selection::default()));
+    }
+}
+
+fn attach_station_sprites(mut commands: Commands,
+                          query: Query<Entity, (Added<Rendered>, With<Station>)>) {
+    for entity in query.iter() {
+        commands.entity(entity).insert((sprite(STATION_COLOR, STATION_SIZE), Selection::default()));
+    }
+}
+
+/// Hull upgrades while the ship is on screen
+fn resize_ship_sprites(mut query: Query<(&HullClass, &mut Sprite), Changed<HullClass>>) {
+    for (hull, mut sprite) in query.iter_mut() {
+        sprite.custom_size = Some(Vec2::splat(hull.sprite_size()));
+    }
+}
+
+/// Child sprite of an off-screen entity pointing toward where it really is
+#[derive(Component)]
+pub struct EdgePointer(Entity);
+
+fn pointer_transform(direction: Vec2) -> Transform {
+    Transform {
+        translation: (direction * POINTER_DISTANCE).extend(1.0),
+        rotation: Quat::from_rotation_z(direction.y.atan2(direction.x)),
+        ..default()
+    }
+}
+
+fn attach_edge_pointers(mut commands: Commands,
+                        query: Query<(Entity, &Offscreen), (With<Sprite>, Without<EdgePointer>)>) {
+    for (entity, offscreen) in query.iter() {
+        let pointer = commands.spawn(SpriteBundle {
+            transform: pointer_transform(offscreen.direction),
+            ..sprite(POINTER_COLOR, POINTER_SIZE)
+        }).id();
+        commands.entity(entity).add_child(pointer).insert(EdgePointer(pointer));
+    }
+}
+
+fn aim_edge_pointers(query: Query<(&Offscreen, &EdgePointer), Changed<Offscreen>>,
+                     mut pointers: Query<&mut Transform>) {
+    for (offscreen, pointer) in query.iter() {
+        if let Ok(mut transform) = pointers.get_mut(pointer.0) {
+            *transform = pointer_transform(offscreen.direction);
+        }
+    }
+}
+
+/// Back on screen, not shown anymore or docked
+fn detach_edge_pointers(mut commands: Commands,
+                        back: RemovedComponents<Offscreen>,
+                        hidden: RemovedComponents<Rendered>,
+                        docked: RemovedComponents<HullClass>,
+                        query: Query<&EdgePointer>) {
+    for entity in back.iter().chain(hidden.iter()).chain(docked.iter()) {
+        if let Ok(pointer) = query.get(entity) {
+            commands.entity(pointer.0).despawn_recursive();
+            commands.entity(entity).remove::<EdgePointer>().remove::<Offscreen>();
+        }
+    }
+}
+
+/// Solar systems keep their galaxy map quad, everything else goes back to simulation only.
+/// Docked pilots lose their ship, and its sprite with it
+fn detach_sprites(mut commands: Commands,
+                  hidden: RemovedComponents<Rendered>,
+                  docked: RemovedComponents<HullClass>,
+                  sprites: Query<(), (With<Sprite>, Without<SolarSystem>)>) {
+    for entity in hidden.iter().chain(docked.iter()) {
+        if sprites.contains(entity) {
+            commands.entity(entity).remove::<SpriteBundle>().remove::<Selection>();
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use bevy::prelude::*;
+
+use crate::space::activity::{OreMinedEvent, PilotKilledEvent};
+use crate::space::combat::CombatSystem;
+use crate::space::mining::MiningSystem;
+use crate::space::pilot::{EName, Pilot};
+
+pub const MAX_PILOT_LEVEL: u8 = 30;
+
+pub struct ProgressionPlugin;
+
+impl Plugin for ProgressionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(ProgressionSettings::default())
+            .add_event::<PilotLevelUp>()
+            .add_system(award_experience
+                .label(ProgressionSystem::Award)
+                .after(CombatSystem::Fire)
+                .after(MiningSystem::Mine));
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ProgressionSystem {
+    Award,
+}
+
+/// How much experience each activity is worth and when pilots level up
+#[derive(Resource)]
+pub struct ProgressionSettings {
+    /// Total experience needed to reach level `i + 2`
+    pub level_thresholds: Vec<u64>,
+    pub xp_per_kill: u64,
+    pub xp_per_m3_mined: f64,
+}
+
+impl Default for ProgressionSettings {
+    fn default() -> Self {
+        Self {
+            level_thresholds: (2..=MAX_PILOT_LEVEL as u64).map(|lvl| 100 * (lvl - 1) * (lvl - 1)).collect(),
+            xp_per_kill: 50,
+            xp_per_m3_mined: 0.1,
+        }
+    }
+}
+
+impl ProgressionSettings {
+    /// Experience needed to go from `level` to the next one, `None` at max level
+    pub fn threshold(&self, level: u8) -> Option<u64> {
+        self.level_thresholds.get(level.checked_sub(1)? as usize).copied()
+    }
+}
+
+pub struct PilotLevelUp {
+    pub pilot: Entity,
+    pub level: u8,
+}
+
+fn award_experience(settings: Res<ProgressionSettings>,
+                    mut kills: EventReader<PilotKilledEvent>,
+                    mut mined: EventReader<OreMinedEvent>,
+                    mut pilots: Query<(&mut Pilot, Option<&EName>)>,
+                    mut level_ups: EventWriter<PilotLevelUp>) {
+    let gains = kills.iter().map(|ev| (ev.killer, settings.xp_per_kill))
+        .chain(mined.iter().map(|ev| (ev.pilot, (ev.volume * settings.xp_per_m3_mined) as u64)));
+
+    for (entity, xp) in gains {
+        let Ok((mut pilot, name)) = pilots.get_mut(entity) else { continue; };
+        pilot.experience += xp;
+        while let Some(needed) = settings.threshold(pilot.level) {
+            if pilot.experience < needed {
+                break;
+            }
+            pilot.level += 1;
+            level_ups.send(PilotLevelUp { pilot: entity, level: pilot.level });
+            info!("{} reached level {}", name.map_or("unnamed pilot", |n| n.0.as_str()), pilot.level);
+        }
+    }
+}
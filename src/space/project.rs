@@ -1,36 +1,150 @@
-use bevy::{ecs::{entity::Entities, query}, prelude::*};
-use bevy::ecs::query::QueryEntityError;
-use bevy::math::DVec3;
-use bevy_mod_picking::{DefaultPickingPlugins, PickableBundle, PickingCameraBundle, PickingEvent};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::math::DVec2;
+use serde::{Deserialize, Serialize};
 
 use crate::{SimPosition, SolarSystem};
-use crate::camera::{CameraID, CameraZoom};
-use crate::space::galaxy::Rendered;
+use crate::camera::{CameraID, CameraOrigin, CameraZoom};
+use crate::base::settings::GameplaySettings;
+use crate::base::units::Pixels;
+use crate::space::galaxy::{GalaxyScale, Rendered, ViewState};
 
+/// What to do with rendered entities outside of the screen
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum OffscreenMode {
+    /// Hide them
+    Cull,
+    /// Pin them to the screen border, pointing toward where they are
+    EdgeIndicator,
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ProjectionSystem {
+    Rebase,
+}
+
+/// Cursor in the system view, in the space of projected translations or in sim units
+#[derive(SystemParam)]
+pub struct ViewCursor<'w, 's> {
+    windows: Res<'w, Windows>,
+    zoom: Res<'w, CameraZoom>,
+    scale: Res<'w, GalaxyScale>,
+    origin: Res<'w, CameraOrigin>,
+    camera_id: Res<'w, CameraID>,
+    cameras: Query<'w, 's, &'static Transform, With<Camera>>,
+}
+
+impl<'w, 's> ViewCursor<'w, 's> {
+    /// Same space as the translation [`project_to_camera`] gives, `None` when the cursor is outside the window
+    pub fn world(&self) -> Option<Vec2> {
+        let window = self.windows.get_primary()?;
+        let cursor = window.cursor_position()?;
+        let camera = self.cameras.get(self.camera_id.0).ok()?;
+        Some(camera.translation.truncate() + cursor - Vec2::new(window.width(), window.height()) / 2.0)
+    }
+
+    /// Sim position projected at `world`, on the plane of the camera origin
+    pub fn to_sim(&self, world: Vec2) -> DVec2 {
+        self.origin.0.truncate() + world.as_dvec2() * self.zoom.sim_per_pixel(&self.scale).0
+    }
+}
+
+/// Rendered entity currently outside of the screen
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Offscreen {
+    /// Screen direction from the center toward the entity, normalized
+    pub direction: Vec2,
+}
+
+/// Fold the camera translation into the sim-space origin so projected coordinates stay small.
+/// The galaxy view isn't projected, its camera translation is kept aside while a system is shown
+pub fn rebase_camera_origin(state: Res<State<ViewState>>,
+                            camera_zoom: Res<CameraZoom>,
+                            scale: Res<GalaxyScale>,
+                            camera_id: Res<CameraID>,
+                            mut origin: ResMut<CameraOrigin>,
+                            mut camera_query: Query<&mut Transform, With<Camera>>,
+                            mut galaxy_translation: Local<Option<Vec3>>) {
+    let Ok(mut transf) = camera_query.get_mut(camera_id.0) else { return; };
+    let in_system = *state.current() == ViewState::SYSTEM;
+
+    if in_system && galaxy_translation.is_none() {
+        //entering a system, start at its center
+        *galaxy_translation = Some(transf.translation);
+        origin.0 = Default::default();
+        transf.translation.x = 0.0;
+        transf.translation.y = 0.0;
+    } else if !in_system {
+        if let Some(previous) = galaxy_translation.take() {
+            transf.translation = previous;
+        }
+        return;
+    }
+
+    let offset = transf.translation.truncate().as_dvec2() * camera_zoom.sim_per_pixel(&scale).0;
+    origin.0.x += offset.x;
+    origin.0.y += offset.y;
+    transf.translation.x = 0.0;
+    transf.translation.y = 0.0;
+}
+
+/// Place rendered entities on screen relative to [`CameraOrigin`], f64 until the final offset
 pub fn project_to_camera(camera_zoom: Res<CameraZoom>,
+                         scale: Res<GalaxyScale>,
+                         settings: Res<GameplaySettings>,
+                         origin: Res<CameraOrigin>,
                          camera_id: Res<CameraID>,
                          camera_query: Query<(&Camera, &Transform)>,
-                         mut query: Query<(&mut Transform, &SimPosition), (With<Rendered>, Without<SolarSystem>, Without<Camera>)>) {
-
-    //println!("projecting {:?} objects ", query.iter().len());
-    let got: Result<(&Camera, &Transform), QueryEntityError> = camera_query.get(camera_id.0);
-    match got {
-        Ok(cam) => {
-            let camera: &Camera = cam.0;
-            let transf: &Transform = cam.1;
-            for (mut trans, sPos) in query.iter_mut() {
-                let calc = Vec3 {
-                    x: ((sPos.0.x / (camera_zoom.0 * 0.000001)) as f32).clamp(
-                        (transf.translation.x - ((camera.physical_viewport_size().unwrap().x - 48) / 2) as f32),
-                        (transf.translation.x + ((camera.physical_viewport_size().unwrap().x - 48) / 2) as f32)),
-                    y: ((sPos.0.y / (camera_zoom.0 * 0.000001)) as f32).clamp(
-                        (transf.translation.y - ((camera.physical_viewport_size().unwrap().y - 48) / 2) as f32),
-                        (transf.translation.y + ((camera.physical_viewport_size().unwrap().y - 48) / 2) as f32)),
-                    z: 0.0,
-                };
-                trans.translation = calc
+                         mut commands: Commands,
+                         mut query: Query<(Entity, &mut Transform, &mut Visibility, &SimPosition, Option<&mut Offscreen>), (With<Rendered>, Without<SolarSystem>, Without<Camera>)>) {
+    let Ok((camera, transf)) = camera_query.get(camera_id.0) else { return; };
+    let Some(viewport) = camera.logical_viewport_size() else { return; };
+
+    let sim_per_pixel = camera_zoom.sim_per_pixel(&scale).0;
+    let center = transf.translation.truncate().as_dvec2();
+    let half = viewport.as_dvec2() / 2.0;
+    let margin = Pixels(settings.offscreen_margin as f64);
+    let inner = (half - margin.0).max(DVec2::ZERO);
+
+    for (entity, mut trans, mut vis, s_pos, offscreen) in query.iter_mut() {
+        let relative = (s_pos.0 - origin.0).truncate() / sim_per_pixel - center;
+        let outside = relative.x.abs() > half.x || relative.y.abs() > half.y;
+
+        let on_screen = match (outside, settings.offscreen_mode) {
+            (true, OffscreenMode::EdgeIndicator) => to_border(relative, inner),
+            _ => relative,
+        };
+        let visible = !(outside && settings.offscreen_mode == OffscreenMode::Cull);
+        if vis.is_visible != visible {
+            vis.is_visible = visible;
+        }
+        let direction = relative.normalize_or_zero().as_vec2();
+        match (outside, offscreen) {
+            (true, None) => { commands.entity(entity).insert(Offscreen { direction }); }
+            (true, Some(mut offscreen)) => {
+                if offscreen.direction != direction {
+                    offscreen.direction = direction;
+                }
             }
+            (false, Some(_)) => { commands.entity(entity).remove::<Offscreen>(); }
+            (false, None) => {}
         }
-        Err(_) => {}
+
+        trans.translation = Vec3 {
+            x: (on_screen.x + center.x) as f32,
+            y: (on_screen.y + center.y) as f32,
+            z: 0.0,
+        };
+    }
+}
+
+/// Where the line from the screen center toward `relative` crosses the `inner` border,
+/// so the indicator keeps the true direction instead of sliding along the edge
+fn to_border(relative: DVec2, inner: DVec2) -> DVec2 {
+    let reach = (relative.x.abs() / inner.x.max(1.0)).max(relative.y.abs() / inner.y.max(1.0));
+    if reach <= 1.0 {
+        return relative;
     }
-}
\ No newline at end of file
+    relative / reach
+}
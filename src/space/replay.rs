@@ -0,0 +1,550 @@
+//! Deterministic replays : a recording holds the universe at its first tick, the seed and every
+//! order with the tick it was applied on. Replaying loads that universe, runs the same fixed ticks
+//! and feeds the player orders back, the AI gives its own again. Both sides hash the state
+//! every [`HASH_INTERVAL`] ticks so the first divergent tick can be reported.
+
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use bevy::app::AppExit;
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::base::rng::SimRng;
+use crate::base::scheduler::{JobClock, Scheduler};
+use crate::base::timer::{SimClock, SimTick};
+use crate::base::velocity::Velocity;
+use crate::space::cargo::Item;
+use crate::space::dev_commands::{apply_dev_action, DevAction, DevActionEvent, StandingTarget};
+use crate::space::galaxy::SimPosition;
+use crate::space::orders::{OrderEvent, OrderSource, OrderSystem, ShipOrder};
+use crate::space::pilot::{Faction, Pilot, PilotIndex};
+use crate::space::save::{LoadRemap, SAVE_VERSION, SaveError, SaveGame, UniverseQuery};
+use crate::space::ship::HullClass;
+
+/// Bump this whenever the replay format changes, old replays are not migrated
+pub const REPLAY_VERSION: u32 = 3;
+/// Length of a tick in recordings and replays
+pub const REPLAY_STEP: Duration = Duration::from_nanos(16_666_667);
+/// Ticks between two state hashes
+pub const HASH_INTERVAL: u64 = 60;
+/// Ticks between two writes of the recording, so a crash loses little
+const WRITE_INTERVAL: u64 = 600;
+
+/// Entity an order refers to, pilots by unique id since they may be spawned after the start
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum ReplayRef {
+    Pilot(u64),
+    /// Bits of an entity of the initial save
+    Saved(u64),
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum ReplayOrder {
+    Navigate([f64; 3]),
+    Warp([f64; 3]),
+    Dock(ReplayRef),
+    Approach(ReplayRef),
+    Orbit(ReplayRef),
+    Lock(ReplayRef),
+    Fire(ReplayRef),
+    Jump(ReplayRef),
+    Stop,
+    Undock,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct RecordedOrder {
+    pub tick: u64,
+    pub ship: ReplayRef,
+    pub order: ReplayOrder,
+    pub source: OrderSource,
+}
+
+/// [`DevAction`] of a console command, factions by index
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum ReplayAction {
+    SpawnShip { system: ReplayRef, hull: HullClass, faction: u32 },
+    Teleport { ship: ReplayRef, system: ReplayRef, at: [f64; 3] },
+    PlaneLock { ship: ReplayRef, z: Option<f64> },
+    Give { pilot: ReplayRef, item: Item, volume: f64 },
+    FactionStanding { from: u32, to: u32, value: f32 },
+    PilotStanding { from: u32, pilot_uid: u64, value: f32 },
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct RecordedAction {
+    pub tick: u64,
+    pub action: ReplayAction,
+}
+
+/// On-disk recording, written as gzipped RON like saves
+#[derive(Serialize, Deserialize)]
+pub struct ReplayFile {
+    pub version: u32,
+    pub seed: u64,
+    /// Length of a tick, kept exact since float seconds shift when sim jobs fall due
+    pub step: Duration,
+    /// Tick at which `initial` was taken, the first replayed tick is the next one
+    pub start_tick: u64,
+    pub initial: SaveGame,
+    pub orders: Vec<RecordedOrder>,
+    pub actions: Vec<RecordedAction>,
+    /// (tick, state hash)
+    pub hashes: Vec<(u64, u64)>,
+}
+
+pub fn write_replay(path: &Path, replay: &ReplayFile) -> Result<(), SaveError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let text = ron::to_string(replay)?;
+    let mut encoder = GzEncoder::new(File::create(path)?, Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    encoder.finish()?;
+    Ok(())
+}
+
+pub fn read_replay(path: &Path) -> Result<ReplayFile, SaveError> {
+    let mut text = String::new();
+    GzDecoder::new(File::open(path)?).read_to_string(&mut text)?;
+    let replay: ReplayFile = ron::from_str(&text)?;
+    if replay.version != REPLAY_VERSION {
+        return Err(SaveError::UnsupportedVersion(replay.version));
+    }
+    if replay.initial.version != SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(replay.initial.version));
+    }
+    Ok(replay)
+}
+
+type HashedPilot = (&'static Pilot, Option<(&'static SimPosition, &'static Velocity, &'static HullClass)>);
+
+/// Hash of what pilots became, in pilot order so the entity layout doesn't matter
+pub fn state_hash<'a>(pilots: impl Iterator<Item = (&'a Pilot, Option<(&'a SimPosition, &'a Velocity, &'a HullClass)>)>) -> u64 {
+    let mut pilots: Vec<_> = pilots.collect();
+    pilots.sort_by_key(|(pilot, _)| pilot.u_id);
+
+    let mut hasher = StateHasher::default();
+    for (pilot, ship) in pilots {
+        hasher.write_u64(pilot.u_id);
+        hasher.write_u8(pilot.level);
+        hasher.write_u64(pilot.experience);
+        if let Some((pos, vel, hull)) = ship {
+            hash_vec(pos.0, &mut hasher);
+            hash_vec(vel.0, &mut hasher);
+            hasher.write_u8(*hull as u8);
+        }
+    }
+    hasher.finish()
+}
+
+fn hash_vec(v: DVec3, hasher: &mut StateHasher) {
+    for c in v.to_array() {
+        hasher.write_u64(c.to_bits());
+    }
+}
+
+/// 64 bit FNV-1a over little endian integers, its output never changes with the Rust release
+/// or the platform unlike `DefaultHasher`, so replays recorded by another build still match
+struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.write(&[i]);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+}
+
+/// Records the run into `path`, needs a fixed [`SimClock`](crate::base::timer::SimClock) step
+pub struct RecorderPlugin {
+    pub path: PathBuf,
+}
+
+impl Plugin for RecorderPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(Recorder { path: self.path.clone(), file: None })
+            .add_system(record_orders.after(OrderSystem::Apply))
+            .add_system(record_actions)
+            .add_system_to_stage(CoreStage::Last, record_tick);
+    }
+}
+
+#[derive(Resource)]
+pub struct Recorder {
+    path: PathBuf,
+    /// `None` until the first tick is over
+    file: Option<ReplayFile>,
+}
+
+impl Recorder {
+    fn write(&self) {
+        let Some(file) = &self.file else { return; };
+        match write_replay(&self.path, file) {
+            Ok(_) => info!("replay written to {:?}, {} orders", self.path, file.orders.len()),
+            Err(e) => error!("could not write replay to {:?} : {:?}", self.path, e),
+        }
+    }
+}
+
+fn to_replay_ref(entity: Entity, pilots: &Query<&Pilot>) -> ReplayRef {
+    match pilots.get(entity) {
+        Ok(pilot) => ReplayRef::Pilot(pilot.u_id),
+        Err(_) => ReplayRef::Saved(entity.to_bits()),
+    }
+}
+
+fn record_orders(mut recorder: ResMut<Recorder>,
+                 tick: Res<SimTick>,
+                 mut orders: EventReader<OrderEvent>,
+                 pilots: Query<&Pilot>) {
+    let Recorder { file, .. } = &mut *recorder;
+    for ev in orders.iter() {
+        //orders of the first tick are already in the initial universe
+        let Some(file) = file.as_mut() else { continue; };
+        let order = match ev.order {
+            ShipOrder::Navigate(at) => ReplayOrder::Navigate(at),
+            ShipOrder::Warp(at) => ReplayOrder::Warp(at),
+            ShipOrder::Dock(station) => ReplayOrder::Dock(to_replay_ref(station, &pilots)),
+            ShipOrder::Approach(target) => ReplayOrder::Approach(to_replay_ref(target, &pilots)),
+            ShipOrder::Orbit(target) => ReplayOrder::Orbit(to_replay_ref(target, &pilots)),
+            ShipOrder::Lock(target) => ReplayOrder::Lock(to_replay_ref(target, &pilots)),
+            ShipOrder::Fire(target) => ReplayOrder::Fire(to_replay_ref(target, &pilots)),
+            ShipOrder::Jump(gate) => ReplayOrder::Jump(to_replay_ref(gate, &pilots)),
+            ShipOrder::Stop => ReplayOrder::Stop,
+            ShipOrder::Undock => ReplayOrder::Undock,
+        };
+        file.orders.push(RecordedOrder {
+            tick: tick.0,
+            ship: to_replay_ref(ev.ship, &pilots),
+            order,
+            source: ev.source,
+        });
+    }
+}
+
+fn record_actions(mut recorder: ResMut<Recorder>,
+                  tick: Res<SimTick>,
+                  mut actions: EventReader<DevActionEvent>,
+                  pilots: Query<&Pilot>) {
+    let Recorder { file, .. } = &mut *recorder;
+    for DevActionEvent(action) in actions.iter() {
+        //like orders, those of the first tick are already in the initial universe
+        let Some(file) = file.as_mut() else { continue; };
+        let action = match *action {
+            DevAction::SpawnShip { system, hull, faction } =>
+                ReplayAction::SpawnShip { system: to_replay_ref(system, &pilots), hull, faction: faction.0 },
+            DevAction::Teleport { ship, system, at } =>
+                ReplayAction::Teleport { ship: to_replay_ref(ship, &pilots), system: to_replay_ref(system, &pilots), at: at.to_array() },
+            DevAction::PlaneLock { ship, z } => ReplayAction::PlaneLock { ship: to_replay_ref(ship, &pilots), z },
+            DevAction::Give { pilot, item, volume } => ReplayAction::Give { pilot: to_replay_ref(pilot, &pilots), item, volume },
+            DevAction::Standing { from, towards: StandingTarget::Faction(to), value } =>
+                ReplayAction::FactionStanding { from: from.0, to: to.0, value },
+            DevAction::Standing { from, towards: StandingTarget::Pilot(pilot_uid), value } =>
+                ReplayAction::PilotStanding { from: from.0, pilot_uid, value },
+        };
+        file.actions.push(RecordedAction { tick: tick.0, action });
+    }
+}
+
+/// Takes the initial universe on the first tick, then hashes and writes as the run goes
+fn record_tick(mut recorder: ResMut<Recorder>,
+               tick: Res<SimTick>,
+               mut rng: ResMut<SimRng>,
+               clock: Res<SimClock>,
+               mut scheduler: ResMut<Scheduler>,
+               universe: UniverseQuery,
+               pilots: Query<HashedPilot>,
+               exits: EventReader<AppExit>) {
+    if recorder.file.is_none() {
+        //the replay loads this universe during its next frame, which restarts the sim jobs
+        //from the time of that frame and the random sequence
+        let seed = rng.seed();
+        rng.reseed(seed);
+        scheduler.restart(JobClock::Sim, clock.elapsed() + clock.delta());
+        recorder.file = Some(ReplayFile {
+            version: REPLAY_VERSION,
+            seed,
+            step: REPLAY_STEP,
+            start_tick: tick.0,
+            initial: universe.snapshot(),
+            orders: Vec::new(),
+            actions: Vec::new(),
+            hashes: Vec::new(),
+        });
+        info!("recording from tick {} with seed {}", tick.0, seed);
+        return;
+    }
+
+    let file = recorder.file.as_mut().unwrap();
+    let elapsed = tick.0 - file.start_tick;
+    if elapsed.is_multiple_of(HASH_INTERVAL) {
+        file.hashes.push((tick.0, state_hash(pilots.iter())));
+    }
+    if elapsed.is_multiple_of(WRITE_INTERVAL) || !exits.is_empty() {
+        recorder.write();
+    }
+}
+
+/// Plays a recording back, the universe, tick, seed and clock must already be set from the file.
+/// The app exits at the first divergent hash, with `diverged` raised
+pub struct ReplayPlugin {
+    pub orders: Vec<RecordedOrder>,
+    pub actions: Vec<RecordedAction>,
+    pub hashes: Vec<(u64, u64)>,
+    pub diverged: Arc<AtomicBool>,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let end = self.hashes.iter().map(|(t, _)| *t)
+            .chain(self.orders.iter().map(|o| o.tick))
+            .chain(self.actions.iter().map(|a| a.tick))
+            .max()
+            .unwrap_or_default();
+        app
+            .insert_resource(Replay {
+                orders: self.orders.clone(),
+                next_order: 0,
+                actions: self.actions.clone(),
+                next_action: 0,
+                hashes: self.hashes.clone(),
+                next_hash: 0,
+                end,
+                diverged: self.diverged.clone(),
+            })
+            .add_system_to_stage(CoreStage::PreUpdate, inject_orders)
+            .add_system_to_stage(CoreStage::PreUpdate, inject_actions)
+            .add_system_to_stage(CoreStage::Last, check_hashes);
+    }
+}
+
+#[derive(Resource)]
+pub struct Replay {
+    orders: Vec<RecordedOrder>,
+    next_order: usize,
+    actions: Vec<RecordedAction>,
+    next_action: usize,
+    hashes: Vec<(u64, u64)>,
+    next_hash: usize,
+    /// Last tick with something to replay or check
+    end: u64,
+    diverged: Arc<AtomicBool>,
+}
+
+/// Feed back the player orders of this tick, they are applied on the same tick as when recorded
+fn inject_orders(mut replay: ResMut<Replay>,
+                 tick: Res<SimTick>,
+                 remap: Option<Res<LoadRemap>>,
+                 index: Res<PilotIndex>,
+                 mut orders: EventWriter<OrderEvent>) {
+    let resolve = |r: ReplayRef| match r {
+        ReplayRef::Pilot(u_id) => index.get(u_id),
+        ReplayRef::Saved(bits) => remap.as_ref().and_then(|m| m.get(bits)),
+    };
+
+    while let Some(recorded) = replay.orders.get(replay.next_order).copied() {
+        if recorded.tick > tick.0 {
+            break;
+        }
+        replay.next_order += 1;
+        if recorded.source != OrderSource::Player {
+            continue;
+        }
+
+        let order = match recorded.order {
+            ReplayOrder::Navigate(at) => Some(ShipOrder::Navigate(at)),
+            ReplayOrder::Warp(at) => Some(ShipOrder::Warp(at)),
+            ReplayOrder::Dock(station) => resolve(station).map(ShipOrder::Dock),
+            ReplayOrder::Approach(target) => resolve(target).map(ShipOrder::Approach),
+            ReplayOrder::Orbit(target) => resolve(target).map(ShipOrder::Orbit),
+            ReplayOrder::Lock(target) => resolve(target).map(ShipOrder::Lock),
+            ReplayOrder::Fire(target) => resolve(target).map(ShipOrder::Fire),
+            ReplayOrder::Jump(gate) => resolve(gate).map(ShipOrder::Jump),
+            ReplayOrder::Stop => Some(ShipOrder::Stop),
+            ReplayOrder::Undock => Some(ShipOrder::Undock),
+        };
+        match (resolve(recorded.ship), order) {
+            (Some(ship), Some(order)) => orders.send(OrderEvent { ship, order, source: OrderSource::Player }),
+            _ => warn!("tick {} : order {:?} of {:?} refers to an unknown entity, skipped", recorded.tick, recorded.order, recorded.ship),
+        }
+    }
+}
+
+/// Apply the console commands of this tick again, before `Update` like the console does
+fn inject_actions(world: &mut World) {
+    let tick = world.resource::<SimTick>().0;
+    let mut due = Vec::new();
+    let mut replay = world.resource_mut::<Replay>();
+    while let Some(recorded) = replay.actions.get(replay.next_action).copied() {
+        if recorded.tick > tick {
+            break;
+        }
+        replay.next_action += 1;
+        due.push(recorded);
+    }
+
+    for recorded in due {
+        let resolve = |r: ReplayRef| match r {
+            ReplayRef::Pilot(u_id) => world.resource::<PilotIndex>().get(u_id),
+            ReplayRef::Saved(bits) => world.get_resource::<LoadRemap>().and_then(|m| m.get(bits)),
+        };
+        let action = match recorded.action {
+            ReplayAction::SpawnShip { system, hull, faction } =>
+                resolve(system).map(|system| DevAction::SpawnShip { system, hull, faction: Faction(faction) }),
+            ReplayAction::Teleport { ship, system, at } => resolve(ship).zip(resolve(system))
+                .map(|(ship, system)| DevAction::Teleport { ship, system, at: DVec3::from_array(at) }),
+            ReplayAction::PlaneLock { ship, z } => resolve(ship).map(|ship| DevAction::PlaneLock { ship, z }),
+            ReplayAction::Give { pilot, item, volume } => resolve(pilot).map(|pilot| DevAction::Give { pilot, item, volume }),
+            ReplayAction::FactionStanding { from, to, value } =>
+                Some(DevAction::Standing { from: Faction(from), towards: StandingTarget::Faction(Faction(to)), value }),
+            ReplayAction::PilotStanding { from, pilot_uid, value } =>
+                Some(DevAction::Standing { from: Faction(from), towards: StandingTarget::Pilot(pilot_uid), value }),
+        };
+        let result = action.ok_or_else(|| String::from("refers to an unknown entity"))
+            .and_then(|action| apply_dev_action(world, action));
+        if let Err(e) = result {
+            warn!("tick {} : action {:?} skipped, {}", recorded.tick, recorded.action, e);
+        }
+    }
+}
+
+fn check_hashes(mut replay: ResMut<Replay>,
+                tick: Res<SimTick>,
+                pilots: Query<HashedPilot>,
+                mut exit: EventWriter<AppExit>) {
+    if let Some((at, expected)) = replay.hashes.get(replay.next_hash).copied() {
+        if at == tick.0 {
+            let actual = state_hash(pilots.iter());
+            if actual != expected {
+                error!("replay diverged at tick {} : expected state {:016x}, got {:016x}", at, expected, actual);
+                replay.diverged.store(true, Ordering::Relaxed);
+                exit.send(AppExit);
+                return;
+            }
+            replay.next_hash += 1;
+        }
+    }
+
+    if tick.0 >= replay.end {
+        info!("replay matched {} state hashes up to tick {}", replay.next_hash, tick.0);
+        exit.send(AppExit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::BaseLogicPlugins;
+    use crate::base::camera::CameraControllerPlugin;
+    use crate::base::console::{ConsoleOverlayPlugin, ConsoleState};
+    use crate::base::hud::HudPlugin;
+    use crate::base::timer::TimerPlugin;
+    use crate::space::{GalaxyViewPlugin, SpaceGamePlugins};
+    use crate::space::context_menu::ContextMenuPlugin;
+    use crate::space::overview::OverviewPlugin;
+    use crate::space::presentation::PresentationPlugin;
+    use crate::space::save::PendingLoad;
+    use crate::space::selection::SelectionPlugin;
+
+    use super::*;
+
+    /// Past the first mining job, so sim jobs are replayed too
+    const TICKS: u64 = 700;
+
+    /// The simulation as `--headless` runs it
+    fn sim_app(rng: SimRng, step: Duration) -> App {
+        let mut app = App::new();
+        app
+            .add_plugins(MinimalPlugins)
+            .add_plugins(BaseLogicPlugins.build()
+                .disable::<CameraControllerPlugin>()
+                .disable::<ConsoleOverlayPlugin>()
+                .disable::<HudPlugin>())
+            .add_plugins(SpaceGamePlugins.build()
+                .disable::<GalaxyViewPlugin>()
+                .disable::<PresentationPlugin>()
+                .disable::<OverviewPlugin>()
+                .disable::<SelectionPlugin>()
+                .disable::<ContextMenuPlugin>())
+            .add_plugin(TimerPlugin)
+            .insert_resource(rng)
+            .insert_resource(SimClock::fixed(step));
+        app
+    }
+
+    #[test]
+    fn replay_matches_recording() {
+        let path = std::env::temp_dir().join(format!("saloon-test-{}.replay", std::process::id()));
+        let mut recording = sim_app(SimRng::seeded(7), REPLAY_STEP);
+        recording
+            .add_startup_system(crate::setup)
+            .add_plugin(RecorderPlugin { path: path.clone() });
+        for tick in 0..TICKS {
+            if tick == TICKS / 4 {
+                //a player order the replay has to feed back
+                let ship = recording.world.query_filtered::<Entity, (With<Pilot>, With<SimPosition>)>()
+                    .iter(&recording.world)
+                    .next()
+                    .expect("no ship in the universe");
+                recording.world.resource_mut::<Events<OrderEvent>>().send(OrderEvent {
+                    ship,
+                    order: ShipOrder::Navigate([1e6, 0.0, 0.0]),
+                    source: OrderSource::Player,
+                });
+                //and console commands, run on the next tick
+                let mut console = recording.world.resource_mut::<ConsoleState>();
+                console.submit("spawn_ship 0 cruiser 2");
+                console.submit("standing 0 faction 2 -10");
+            }
+            recording.update();
+        }
+        let file = recording.world.resource_mut::<Recorder>().file.take().unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(file.orders.iter().any(|o| o.source == OrderSource::Player));
+        assert_eq!(file.actions.len(), 2);
+        assert_eq!(file.hashes.len() as u64, (TICKS - 1) / HASH_INTERVAL);
+
+        let diverged = Arc::new(AtomicBool::new(false));
+        let hashes = file.hashes.len();
+        let mut replay = sim_app(SimRng::seeded(file.seed), file.step);
+        replay
+            .insert_resource(SimTick(file.start_tick))
+            .add_plugin(ReplayPlugin { orders: file.orders, actions: file.actions, hashes: file.hashes, diverged: diverged.clone() })
+            .insert_resource(PendingLoad::Save(Box::new(file.initial)));
+        for _ in 0..TICKS {
+            replay.update();
+        }
+        assert!(!diverged.load(Ordering::Relaxed));
+        assert_eq!(replay.world.resource::<Replay>().next_hash, hashes);
+    }
+}
@@ -0,0 +1,509 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::math::DVec3;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use self::migration::MigrationReport;
+
+use crate::base::actions::{Action, ActionState};
+use crate::base::scheduler::{Job, JobClock, job_due, Scheduler};
+use crate::base::timer::SimClock;
+use crate::base::velocity::{PlaneLock, Velocity};
+use crate::space::faction::{FactionDef, FactionRegistry, Standings};
+use crate::space::galaxy::{AnomalyMining, GalaxyCoordinate, SimPosition, SolarSystem, spawn_solar_system, SystemMap};
+use crate::space::mining::ore_field;
+use crate::space::cargo::{Cargo, Item};
+use crate::space::pilot::{EName, Faction, NameLexicon, Pilot, PilotBundle, PilotNameGenerator, RespawnBase};
+use crate::space::security::{Police, PoliceFaction, register_navy, SecurityStatus};
+use crate::space::ship::{Destination, DestoType, HullClass, new_ship, UndockingFrom, UndockLoc};
+use crate::space::skills::{Attribute, PilotAttributes, SkillBonuses, SkillId, Skills, SkillTree, TrainedSkill, TrainingQueue};
+use crate::space::station::{spawn_station_at, Station};
+
+pub mod migration;
+
+/// Bump this and add a step in [`migration`] whenever the format changes
+pub const SAVE_VERSION: u32 = 5;
+
+pub const AUTOSAVE_PATH: &str = "saves/autosave.sav";
+pub const QUICKSAVE_PATH: &str = "saves/quicksave.sav";
+/// Real time between two autosaves
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(300);
+const AUTOSAVE_JOB: &str = "autosave";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<SaveRequest>()
+            .add_startup_system_to_stage(StartupStage::PostStartup, load_on_startup.label(SaveSystem::Load))
+            .add_system(autosave.with_run_criteria(job_due(AUTOSAVE_JOB)))
+            .add_system(quicksave_input)
+            .add_system(save_universe);
+        app.world.get_resource_or_insert_with(Scheduler::default)
+            .add(AUTOSAVE_JOB, Job::every(JobClock::Real, AUTOSAVE_INTERVAL));
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SaveSystem {
+    Load,
+}
+
+/// Universe to load at startup, the default setup must not run when this is present
+#[derive(Resource)]
+pub enum PendingLoad {
+    File(PathBuf),
+    /// Already in memory, e.g. the start of a replay
+    Save(Box<SaveGame>),
+}
+
+/// Entity each saved id was given by the last load
+#[derive(Resource, Default)]
+pub struct LoadRemap(pub HashMap<u64, Entity>);
+
+impl LoadRemap {
+    pub fn get(&self, id: u64) -> Option<Entity> {
+        self.0.get(&id).copied()
+    }
+}
+
+/// Write the universe to the given file at the end of the frame
+pub struct SaveRequest(pub PathBuf);
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Deserialize(ron::error::SpannedError),
+    /// Written by a newer build, or too old to be migrated
+    UnsupportedVersion(u32),
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(e: ron::Error) -> Self {
+        SaveError::Serialize(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for SaveError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        SaveError::Deserialize(e)
+    }
+}
+
+/// On-disk universe, written as gzipped RON.
+/// Entities are stored by their bits at save time and only used as references inside the file.
+/// Police ships, pending police responses, damage and warps in progress are transient and not saved.
+/// Markets will get their own section once they exist.
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub factions: Vec<SavedFaction>,
+    pub police_faction: Option<u32>,
+    pub faction_standings: Vec<(u32, u32, f32)>,
+    pub pilot_standings: Vec<(u32, u64, f32)>,
+    pub systems: Vec<SavedSystem>,
+    pub stations: Vec<SavedStation>,
+    /// Ore fields, the only anomalies so far
+    pub anomalies: Vec<SavedAnomaly>,
+    pub pilots: Vec<SavedPilot>,
+    /// (pilot uid, item, m3)
+    pub cargo: Vec<(u64, Item, f64)>,
+    pub next_pilot_uid: u64,
+    /// Seed of the [`PilotNameGenerator`], names of new pilots follow it
+    pub name_seed: u64,
+    /// Seconds of sim time since the epoch, see [`GameDate`](crate::base::timer::GameDate)
+    pub sim_time: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedFaction {
+    pub name: String,
+    pub home_systems: Vec<u64>,
+    pub color: [f32; 4],
+    pub lexicon: NameLexicon,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedSystem {
+    pub id: u64,
+    pub position: [f64; 3],
+    pub security: f32,
+    pub anomalies: Vec<u64>,
+    pub gates: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedStation {
+    pub id: u64,
+    pub system: u64,
+    pub position: [f64; 3],
+    pub owner: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedAnomaly {
+    pub id: u64,
+    pub system: u64,
+    pub position: [f64; 3],
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedPilot {
+    pub id: u64,
+    pub u_id: u64,
+    pub name: String,
+    pub level: u8,
+    pub experience: u64,
+    pub faction: u32,
+    pub attributes: Vec<(Attribute, u8)>,
+    pub respawn_base: Option<u64>,
+    /// (skill, level, points)
+    pub skills: Vec<(u16, u8, f64)>,
+    pub training: Vec<(u16, u8)>,
+    pub undocking_from: Option<u64>,
+    pub ship: Option<SavedShip>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedShip {
+    pub system: u64,
+    pub position: [f64; 3],
+    pub velocity: [f64; 3],
+    pub destination: SavedDestination,
+    pub hull: HullClass,
+    /// Height of the plane the ship is locked on
+    pub plane_lock: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum SavedDestination {
+    Position([f64; 3]),
+    Entity([f64; 3]),
+    None,
+}
+
+impl From<&DestoType> for SavedDestination {
+    fn from(desto: &DestoType) -> Self {
+        match desto {
+            DestoType::DPosition(pos) => SavedDestination::Position(pos.0.to_array()),
+            DestoType::TEntity(pos) => SavedDestination::Entity(pos.0.to_array()),
+            DestoType::None => SavedDestination::None,
+        }
+    }
+}
+
+impl From<&SavedDestination> for DestoType {
+    fn from(saved: &SavedDestination) -> Self {
+        match saved {
+            SavedDestination::Position(pos) => DestoType::DPosition(SimPosition(DVec3::from_array(*pos))),
+            SavedDestination::Entity(pos) => DestoType::TEntity(SimPosition(DVec3::from_array(*pos))),
+            SavedDestination::None => DestoType::None,
+        }
+    }
+}
+
+pub fn write_save(path: &Path, save: &SaveGame) -> Result<(), SaveError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let text = ron::to_string(save)?;
+    let mut encoder = GzEncoder::new(File::create(path)?, Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    encoder.finish()?;
+    Ok(())
+}
+
+fn read_save_text(path: &Path) -> Result<String, SaveError> {
+    let mut text = String::new();
+    GzDecoder::new(File::open(path)?).read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Read a save of any supported version, upgraded to the current format
+pub fn read_save(path: &Path) -> Result<(SaveGame, MigrationReport), SaveError> {
+    migration::upgrade(&read_save_text(path)?)
+}
+
+/// What loading `path` would change, without writing anything
+pub fn dry_run_migration(path: &Path) -> Result<MigrationReport, SaveError> {
+    read_save(path).map(|(_, report)| report)
+}
+
+fn autosave(mut requests: EventWriter<SaveRequest>) {
+    requests.send(SaveRequest(PathBuf::from(AUTOSAVE_PATH)));
+}
+
+fn quicksave_input(actions: Res<ActionState>,
+                   mut requests: EventWriter<SaveRequest>) {
+    if actions.just_pressed(Action::QuickSave) {
+        requests.send(SaveRequest(PathBuf::from(QUICKSAVE_PATH)));
+    }
+}
+
+fn save_universe(mut requests: EventReader<SaveRequest>,
+                 universe: UniverseQuery) {
+    //several requests in the same frame would write the same thing
+    let Some(SaveRequest(path)) = requests.iter().last() else { return; };
+
+    match write_save(path, &universe.snapshot()) {
+        Ok(_) => info!("universe saved to {:?}", path),
+        Err(e) => error!("could not save universe to {:?} : {:?}", path, e),
+    }
+}
+
+/// Everything that goes in a [`SaveGame`]
+#[derive(SystemParam)]
+pub struct UniverseQuery<'w, 's> {
+    clock: Res<'w, SimClock>,
+    registry: Res<'w, FactionRegistry>,
+    standings: Res<'w, Standings>,
+    names: Res<'w, PilotNameGenerator>,
+    police: Option<Res<'w, PoliceFaction>>,
+    systems: Query<'w, 's, (Entity, &'static SolarSystem, &'static SimPosition, Option<&'static SecurityStatus>)>,
+    stations: Query<'w, 's, (Entity, &'static SimPosition, &'static GalaxyCoordinate, &'static Faction), With<Station>>,
+    fields: Query<'w, 's, (Entity, &'static SimPosition, &'static GalaxyCoordinate), With<AnomalyMining>>,
+    pilots: Query<'w, 's, (Entity, &'static Pilot, &'static EName, &'static Faction, &'static PilotAttributes, &'static RespawnBase,
+                           &'static Skills, &'static TrainingQueue, &'static Cargo, Option<&'static UndockingFrom>,
+                           Option<(&'static GalaxyCoordinate, &'static SimPosition, &'static Velocity, &'static Destination, &'static HullClass, Option<&'static PlaneLock>)>),
+                  Without<Police>>,
+}
+
+impl<'w, 's> UniverseQuery<'w, 's> {
+    pub fn snapshot(&self) -> SaveGame {
+        SaveGame {
+            version: SAVE_VERSION,
+            factions: self.registry.0.iter().map(|f| SavedFaction {
+                name: f.name.clone(),
+                home_systems: f.home_systems.iter().map(|e| e.to_bits()).collect(),
+                color: f.color.as_rgba_f32(),
+                lexicon: f.lexicon.clone(),
+            }).collect(),
+            police_faction: self.police.as_ref().map(|p| p.0.0),
+            faction_standings: self.standings.faction_entries().map(|(from, to, v)| (from.0, to.0, v)).collect(),
+            pilot_standings: self.standings.pilot_entries().map(|(from, uid, v)| (from.0, uid, v)).collect(),
+            systems: self.systems.iter().map(|(entity, system, pos, security)| SavedSystem {
+                id: entity.to_bits(),
+                position: pos.0.to_array(),
+                security: security.map_or(0.0, |s| s.0),
+                anomalies: system.anomalies.iter().map(|e| e.to_bits()).collect(),
+                gates: system.gates.iter().map(|e| e.to_bits()).collect(),
+            }).collect(),
+            stations: self.stations.iter().map(|(entity, pos, coord, owner)| SavedStation {
+                id: entity.to_bits(),
+                system: coord.0.to_bits(),
+                position: pos.0.to_array(),
+                owner: owner.0,
+            }).collect(),
+            anomalies: self.fields.iter().map(|(entity, pos, coord)| SavedAnomaly {
+                id: entity.to_bits(),
+                system: coord.0.to_bits(),
+                position: pos.0.to_array(),
+            }).collect(),
+            pilots: self.pilots.iter().map(|(entity, pilot, name, faction, attributes, respawn, skills, training, _, undocking, ship)| SavedPilot {
+                id: entity.to_bits(),
+                u_id: pilot.u_id,
+                name: name.0.clone(),
+                level: pilot.level,
+                experience: pilot.experience,
+                faction: faction.0,
+                attributes: attributes.0.iter().map(|(a, v)| (*a, *v)).collect(),
+                respawn_base: respawn.0.map(|e| e.to_bits()),
+                skills: skills.0.iter().map(|(id, s)| (id.0, s.level, s.points)).collect(),
+                training: training.0.iter().map(|(id, level)| (id.0, *level)).collect(),
+                undocking_from: undocking.map(|u| u.0.to_bits()),
+                ship: ship.map(|(coord, pos, vel, dest, hull, lock)| SavedShip {
+                    system: coord.0.to_bits(),
+                    position: pos.0.to_array(),
+                    velocity: vel.0.to_array(),
+                    destination: SavedDestination::from(&dest.0),
+                    hull: *hull,
+                    plane_lock: lock.map(|l| l.0),
+                }),
+            }).collect(),
+            cargo: self.pilots.iter()
+                .flat_map(|(_, pilot, .., cargo, _, _)| cargo.0.iter().map(|(item, volume)| (pilot.u_id, *item, *volume)))
+                .collect(),
+            next_pilot_uid: self.names.peek_uid(),
+            name_seed: self.names.seed(),
+            sim_time: self.clock.elapsed().as_secs_f64(),
+        }
+    }
+}
+
+/// Rebuild the universe from [`PendingLoad`], new entities get new ids
+/// so every reference in the file goes through a remapping table
+fn load_on_startup(mut commands: Commands,
+                   pending: Option<Res<PendingLoad>>,
+                   mut cluster: ResMut<SystemMap>,
+                   mut registry: ResMut<FactionRegistry>,
+                   mut standings: ResMut<Standings>,
+                   mut names: ResMut<PilotNameGenerator>,
+                   mut clock: ResMut<SimClock>,
+                   mut scheduler: ResMut<Scheduler>,
+                   tree: Res<SkillTree>) {
+    let Some(pending) = pending else { return; };
+    let read;
+    let (save, source) = match &*pending {
+        PendingLoad::Save(save) => (save.as_ref(), String::from("memory")),
+        PendingLoad::File(path) => {
+            read = match read_save(path) {
+                Ok((save, report)) => {
+                    for change in report.changes.iter() {
+                        info!("save migration : {}", change);
+                    }
+                    save
+                }
+                Err(SaveError::UnsupportedVersion(v)) => {
+                    panic!("{:?} has save version {}, this build only reads versions {} to {}",
+                           path, v, migration::OLDEST_SUPPORTED_VERSION, SAVE_VERSION);
+                }
+                Err(e) => {
+                    error!("could not load universe from {:?} : {:?}", path, e);
+                    return;
+                }
+            };
+            (&read, format!("{:?}", path))
+        }
+    };
+
+    let mut remap: HashMap<u64, Entity> = HashMap::new();
+    for system in save.systems.iter() {
+        let entity = spawn_solar_system(
+            &mut commands,
+            DVec3::from_array(system.position),
+            SecurityStatus(system.security),
+        );
+        cluster.0.push(entity);
+        remap.insert(system.id, entity);
+    }
+    for station in save.stations.iter() {
+        remap.insert(station.id, commands.spawn_empty().id());
+    }
+    for anomaly in save.anomalies.iter() {
+        remap.insert(anomaly.id, commands.spawn_empty().id());
+    }
+    for pilot in save.pilots.iter() {
+        remap.insert(pilot.id, commands.spawn_empty().id());
+    }
+    let get = |id: &u64| remap.get(id).copied();
+
+    for system in save.systems.iter() {
+        commands.entity(remap[&system.id]).insert(SolarSystem {
+            anomalies: system.anomalies.iter().filter_map(get).collect(),
+            gates: system.gates.iter().filter_map(get).collect(),
+        });
+    }
+
+    registry.0.clear();
+    for faction in save.factions.iter() {
+        let [r, g, b, a] = faction.color;
+        registry.register(FactionDef {
+            name: faction.name.clone(),
+            home_systems: faction.home_systems.iter().filter_map(get).collect(),
+            color: Color::rgba(r, g, b, a),
+            lexicon: faction.lexicon.clone(),
+        });
+    }
+    names.use_lexicons(&registry);
+    let police = match save.police_faction {
+        Some(police) => Faction(police),
+        None => register_navy(&mut registry, &mut names),
+    };
+    commands.insert_resource(PoliceFaction(police));
+
+    *standings = Standings::default();
+    for (from, to, value) in save.faction_standings.iter() {
+        standings.set_faction(Faction(*from), Faction(*to), *value);
+    }
+    for (from, uid, value) in save.pilot_standings.iter() {
+        standings.set_pilot(Faction(*from), *uid, *value);
+    }
+
+    for station in save.stations.iter() {
+        let Some(system) = get(&station.system) else { continue; };
+        commands.entity(remap[&station.id]).insert((
+            spawn_station_at(SimPosition(DVec3::from_array(station.position)), system, Faction(station.owner)),
+            UndockLoc,
+        ));
+    }
+
+    for anomaly in save.anomalies.iter() {
+        let Some(system) = get(&anomaly.system) else { continue; };
+        commands.entity(remap[&anomaly.id]).insert(ore_field(system, DVec3::from_array(anomaly.position)));
+    }
+
+    for saved in save.pilots.iter() {
+        let entity = remap[&saved.id];
+        let mut skills = Skills::default();
+        for (id, level, points) in saved.skills.iter() {
+            skills.0.insert(SkillId(*id), TrainedSkill { level: *level, points: *points });
+        }
+        let bonuses = SkillBonuses::from_skills(&tree, &skills);
+        names.reserve_uid(saved.u_id);
+
+        commands.entity(entity).insert(PilotBundle {
+            _pilot: Pilot {
+                level: saved.level,
+                experience: saved.experience,
+                u_id: saved.u_id,
+            },
+            respawn_base: RespawnBase(saved.respawn_base.as_ref().and_then(get)),
+            pilot_name: EName(saved.name.clone()),
+            pilot_faction: Faction(saved.faction),
+            attributes: PilotAttributes(saved.attributes.iter().copied().collect()),
+            skills,
+            training: TrainingQueue(saved.training.iter().map(|(id, level)| (SkillId(*id), *level)).collect()),
+            bonuses,
+            cargo: Cargo(save.cargo.iter()
+                .filter(|(u_id, ..)| *u_id == saved.u_id)
+                .map(|(_, item, volume)| (*item, *volume))
+                .collect()),
+        });
+
+        if let Some(from) = saved.undocking_from.as_ref().and_then(get) {
+            commands.entity(entity).insert(UndockingFrom(from));
+        }
+
+        if let Some(ship) = saved.ship.as_ref() {
+            let Some(system) = get(&ship.system) else { continue; };
+            commands.entity(entity)
+                .insert(new_ship(
+                    system,
+                    SimPosition(DVec3::from_array(ship.position)),
+                    DestoType::from(&ship.destination),
+                    ship.hull,
+                ))
+                .insert(Velocity(DVec3::from_array(ship.velocity)));
+            if let Some(z) = ship.plane_lock {
+                commands.entity(entity).insert(PlaneLock(z));
+            }
+        }
+    }
+    names.reserve_uid(save.next_pilot_uid.saturating_sub(1));
+    names.reseed(save.name_seed);
+    //the loading frame already advanced the clock and moves ships by that delta, keep it
+    let elapsed = Duration::from_secs_f64(save.sim_time.max(0.0)) + clock.delta();
+    clock.set_elapsed(elapsed);
+    scheduler.restart(JobClock::Sim, clock.elapsed());
+
+    info!("universe loaded from {}, {} pilots", source, save.pilots.len());
+    commands.insert_resource(LoadRemap(remap));
+    commands.remove_resource::<PendingLoad>();
+}
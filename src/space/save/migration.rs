@@ -0,0 +1,441 @@
+//! Upgrade of older save files to the current [`SaveGame`] format.
+//!
+//! Every format change bumps [`SAVE_VERSION`], freezes the previous shape of the
+//! changed structs in a `vN` module and adds a step turning version N into N+1.
+//! Renamed fields only need a `#[serde(alias = "old_name")]` on the current struct,
+//! fields that did not exist get their default in the step.
+
+use serde::Deserialize;
+
+use super::{SAVE_VERSION, SaveError, SaveGame, SavedDestination, SavedPilot, SavedShip};
+use crate::space::skills::PilotAttributes;
+
+pub const OLDEST_SUPPORTED_VERSION: u32 = 1;
+
+/// What was done, or would be done, to bring a save to the current version
+#[derive(Debug)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    pub changes: Vec<String>,
+}
+
+/// Only reads the version, everything else is ignored
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: u32,
+}
+
+enum VersionedSave {
+    V1(v1::SaveGame),
+    V2(v2::SaveGame),
+    V3(v3::SaveGame),
+    V4(v4::SaveGame),
+    Current(SaveGame),
+}
+
+pub fn detect_version(text: &str) -> Result<u32, SaveError> {
+    let probe: VersionProbe = ron::from_str(text)?;
+    Ok(probe.version)
+}
+
+pub fn upgrade(text: &str) -> Result<(SaveGame, MigrationReport), SaveError> {
+    let from = detect_version(text)?;
+    let mut save = match from {
+        1 => VersionedSave::V1(ron::from_str(text)?),
+        2 => VersionedSave::V2(ron::from_str(text)?),
+        3 => VersionedSave::V3(ron::from_str(text)?),
+        4 => VersionedSave::V4(ron::from_str(text)?),
+        SAVE_VERSION => VersionedSave::Current(ron::from_str(text)?),
+        unknown => return Err(SaveError::UnsupportedVersion(unknown)),
+    };
+
+    let mut changes = Vec::new();
+    loop {
+        save = match save {
+            VersionedSave::V1(old) => VersionedSave::V2(v1_to_v2(old, &mut changes)),
+            VersionedSave::V2(old) => VersionedSave::V3(v2_to_v3(old, &mut changes)),
+            VersionedSave::V3(old) => VersionedSave::V4(v3_to_v4(old, &mut changes)),
+            VersionedSave::V4(old) => VersionedSave::Current(v4_to_v5(old, &mut changes)),
+            VersionedSave::Current(save) => {
+                return Ok((save, MigrationReport { from, to: SAVE_VERSION, changes }));
+            }
+        };
+    }
+}
+
+/// v2 saves pilot attributes, older pilots get the default ones
+fn v1_to_v2(old: v1::SaveGame, changes: &mut Vec<String>) -> v2::SaveGame {
+    changes.push(format!("v1 -> v2 : {} pilots get default attributes", old.pilots.len()));
+    let default_attributes: Vec<_> = PilotAttributes::default().0.into_iter().collect();
+    v2::SaveGame {
+        factions: old.factions,
+        police_faction: old.police_faction,
+        faction_standings: old.faction_standings,
+        pilot_standings: old.pilot_standings,
+        systems: old.systems,
+        stations: old.stations,
+        anomalies: old.anomalies,
+        pilots: old.pilots.into_iter().map(|p| v2::SavedPilot {
+            id: p.id,
+            u_id: p.u_id,
+            name: p.name,
+            level: p.level,
+            experience: p.experience,
+            faction: p.faction,
+            attributes: default_attributes.clone(),
+            respawn_base: p.respawn_base,
+            skills: p.skills,
+            training: p.training,
+            undocking_from: p.undocking_from,
+            ship: p.ship,
+        }).collect(),
+        next_pilot_uid: old.next_pilot_uid,
+        name_seed: old.name_seed,
+    }
+}
+
+/// v3 simulates in 3D, flat velocities and destinations get z = 0
+fn v2_to_v3(old: v2::SaveGame, changes: &mut Vec<String>) -> v3::SaveGame {
+    let ships = old.pilots.iter().filter(|p| p.ship.is_some()).count();
+    changes.push(format!("v2 -> v3 : {} ships moved to 3D velocities and destinations", ships));
+    v3::SaveGame {
+        factions: old.factions,
+        police_faction: old.police_faction,
+        faction_standings: old.faction_standings,
+        pilot_standings: old.pilot_standings,
+        systems: old.systems,
+        stations: old.stations,
+        anomalies: old.anomalies,
+        pilots: old.pilots.into_iter().map(|p| SavedPilot {
+            id: p.id,
+            u_id: p.u_id,
+            name: p.name,
+            level: p.level,
+            experience: p.experience,
+            faction: p.faction,
+            attributes: p.attributes,
+            respawn_base: p.respawn_base,
+            skills: p.skills,
+            training: p.training,
+            undocking_from: p.undocking_from,
+            ship: p.ship.map(|ship| SavedShip {
+                system: ship.system,
+                position: ship.position,
+                velocity: [ship.velocity[0], ship.velocity[1], 0.0],
+                destination: match ship.destination {
+                    v2::SavedDestination::Position([x, y]) => SavedDestination::Position([x, y, 0.0]),
+                    v2::SavedDestination::Entity(pos) => SavedDestination::Entity(pos),
+                    v2::SavedDestination::None => SavedDestination::None,
+                },
+                hull: ship.hull,
+                plane_lock: None,
+            }),
+        }).collect(),
+        next_pilot_uid: old.next_pilot_uid,
+        name_seed: old.name_seed,
+    }
+}
+
+/// v4 saves cargo, pilots had none before
+fn v3_to_v4(old: v3::SaveGame, changes: &mut Vec<String>) -> v4::SaveGame {
+    changes.push(format!("v3 -> v4 : {} pilots start with an empty cargo", old.pilots.len()));
+    v4::SaveGame {
+        factions: old.factions,
+        police_faction: old.police_faction,
+        faction_standings: old.faction_standings,
+        pilot_standings: old.pilot_standings,
+        systems: old.systems,
+        stations: old.stations,
+        anomalies: old.anomalies,
+        pilots: old.pilots,
+        cargo: Vec::new(),
+        next_pilot_uid: old.next_pilot_uid,
+        name_seed: old.name_seed,
+    }
+}
+
+/// v5 keeps the sim clock, older universes start at the epoch
+fn v4_to_v5(old: v4::SaveGame, changes: &mut Vec<String>) -> SaveGame {
+    changes.push(String::from("v4 -> v5 : the sim clock starts at the epoch"));
+    SaveGame {
+        version: 5,
+        factions: old.factions,
+        police_faction: old.police_faction,
+        faction_standings: old.faction_standings,
+        pilot_standings: old.pilot_standings,
+        systems: old.systems,
+        stations: old.stations,
+        anomalies: old.anomalies,
+        pilots: old.pilots,
+        cargo: old.cargo,
+        next_pilot_uid: old.next_pilot_uid,
+        name_seed: old.name_seed,
+        sim_time: 0.0,
+    }
+}
+
+/// Format written by the first save system
+mod v1 {
+    use serde::Deserialize;
+
+    use crate::space::save::{SavedAnomaly, SavedFaction, SavedStation, SavedSystem};
+    use super::v2::SavedShip;
+
+    #[derive(Deserialize)]
+    pub struct SaveGame {
+        pub factions: Vec<SavedFaction>,
+        pub police_faction: Option<u32>,
+        pub faction_standings: Vec<(u32, u32, f32)>,
+        pub pilot_standings: Vec<(u32, u64, f32)>,
+        pub systems: Vec<SavedSystem>,
+        pub stations: Vec<SavedStation>,
+        pub anomalies: Vec<SavedAnomaly>,
+        pub pilots: Vec<SavedPilot>,
+        pub next_pilot_uid: u64,
+        pub name_seed: u64,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SavedPilot {
+        pub id: u64,
+        pub u_id: u64,
+        pub name: String,
+        pub level: u8,
+        pub experience: u64,
+        pub faction: u32,
+        pub respawn_base: Option<u64>,
+        pub skills: Vec<(u16, u8, f64)>,
+        pub training: Vec<(u16, u8)>,
+        pub undocking_from: Option<u64>,
+        pub ship: Option<SavedShip>,
+    }
+}
+
+/// Last 2D format, with pilot attributes
+mod v2 {
+    use serde::Deserialize;
+
+    use crate::space::save::{SavedAnomaly, SavedFaction, SavedStation, SavedSystem};
+    use crate::space::ship::HullClass;
+    use crate::space::skills::Attribute;
+
+    #[derive(Deserialize)]
+    pub struct SaveGame {
+        pub factions: Vec<SavedFaction>,
+        pub police_faction: Option<u32>,
+        pub faction_standings: Vec<(u32, u32, f32)>,
+        pub pilot_standings: Vec<(u32, u64, f32)>,
+        pub systems: Vec<SavedSystem>,
+        pub stations: Vec<SavedStation>,
+        pub anomalies: Vec<SavedAnomaly>,
+        pub pilots: Vec<SavedPilot>,
+        pub next_pilot_uid: u64,
+        pub name_seed: u64,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SavedPilot {
+        pub id: u64,
+        pub u_id: u64,
+        pub name: String,
+        pub level: u8,
+        pub experience: u64,
+        pub faction: u32,
+        pub attributes: Vec<(Attribute, u8)>,
+        pub respawn_base: Option<u64>,
+        pub skills: Vec<(u16, u8, f64)>,
+        pub training: Vec<(u16, u8)>,
+        pub undocking_from: Option<u64>,
+        pub ship: Option<SavedShip>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SavedShip {
+        pub system: u64,
+        pub position: [f64; 3],
+        pub velocity: [f64; 2],
+        pub destination: SavedDestination,
+        pub hull: HullClass,
+    }
+
+    #[derive(Deserialize)]
+    pub enum SavedDestination {
+        Position([f64; 2]),
+        Entity([f64; 3]),
+        None,
+    }
+}
+
+/// Last format without cargo
+mod v3 {
+    use serde::Deserialize;
+
+    use crate::space::save::{SavedAnomaly, SavedFaction, SavedPilot, SavedStation, SavedSystem};
+
+    #[derive(Deserialize)]
+    pub struct SaveGame {
+        pub factions: Vec<SavedFaction>,
+        pub police_faction: Option<u32>,
+        pub faction_standings: Vec<(u32, u32, f32)>,
+        pub pilot_standings: Vec<(u32, u64, f32)>,
+        pub systems: Vec<SavedSystem>,
+        pub stations: Vec<SavedStation>,
+        pub anomalies: Vec<SavedAnomaly>,
+        pub pilots: Vec<SavedPilot>,
+        pub next_pilot_uid: u64,
+        pub name_seed: u64,
+    }
+}
+
+/// Last format without sim clock
+mod v4 {
+    use serde::Deserialize;
+
+    use crate::space::cargo::Item;
+    use crate::space::save::{SavedAnomaly, SavedFaction, SavedPilot, SavedStation, SavedSystem};
+
+    #[derive(Deserialize)]
+    pub struct SaveGame {
+        pub factions: Vec<SavedFaction>,
+        pub police_faction: Option<u32>,
+        pub faction_standings: Vec<(u32, u32, f32)>,
+        pub pilot_standings: Vec<(u32, u64, f32)>,
+        pub systems: Vec<SavedSystem>,
+        pub stations: Vec<SavedStation>,
+        pub anomalies: Vec<SavedAnomaly>,
+        pub pilots: Vec<SavedPilot>,
+        pub cargo: Vec<(u64, Item, f64)>,
+        pub next_pilot_uid: u64,
+        pub name_seed: u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::space::cargo::Item;
+    use crate::space::skills::Attribute;
+
+    /// Sections every version has : a police faction and a trader, one system with a station and its ore field
+    const UNIVERSE: &str = r#"
+        factions: [
+            (name: "Navy", home_systems: [1], color: (0.0, 0.0, 1.0, 1.0),
+                lexicon: (first_syllables: ["ha"], last_syllables: ["rk"], family_syllables: ["ne"])),
+            (name: "Traders", home_systems: [1], color: (1.0, 1.0, 0.0, 1.0),
+                lexicon: (first_syllables: ["ka"], last_syllables: ["n"], family_syllables: ["vel"])),
+        ],
+        police_faction: Some(0),
+        faction_standings: [(1, 0, 0.5)],
+        pilot_standings: [],
+        systems: [(id: 1, position: (0.0, 0.0, 0.0), security: 0.8, anomalies: [4], gates: [])],
+        stations: [(id: 2, system: 1, position: (10.0, 0.0, 0.0), owner: 1)],
+        anomalies: [(id: 4, system: 1, position: (20.0, 0.0, 0.0))],
+        next_pilot_uid: 8,
+        name_seed: 42,
+    "#;
+
+    fn v1_pilot(extra: &str, ship: &str) -> String {
+        format!(r#"(id: 3, u_id: 7, name: "Ana", level: 2, experience: 40, faction: 1, {extra}
+            respawn_base: Some(2), skills: [(0, 1, 250.0)], training: [], undocking_from: None, ship: Some({ship}))"#)
+    }
+
+    const SHIP_2D: &str = r#"(system: 1, position: (1.0, 2.0, 0.0), velocity: (3.0, 4.0),
+        destination: Position((5.0, 6.0)), hull: Frigate)"#;
+    const SHIP_3D: &str = r#"(system: 1, position: (1.0, 2.0, 0.0), velocity: (3.0, 4.0, 0.0),
+        destination: Position((5.0, 6.0, 0.0)), hull: Frigate, plane_lock: None)"#;
+    const ATTRIBUTES: &str = "attributes: [(Perception, 25), (Memory, 15)],";
+
+    fn save_text(version: u32, pilot: String) -> String {
+        format!("(version: {version}, {UNIVERSE} pilots: [{pilot}])")
+    }
+
+    fn v1() -> String {
+        save_text(1, v1_pilot("", SHIP_2D))
+    }
+
+    fn v2() -> String {
+        save_text(2, v1_pilot(ATTRIBUTES, SHIP_2D))
+    }
+
+    fn v3() -> String {
+        save_text(3, v1_pilot(ATTRIBUTES, SHIP_3D))
+    }
+
+    fn v4() -> String {
+        format!("(version: 4, {UNIVERSE} cargo: [(7, Ore, 12.5)], pilots: [{}])", v1_pilot(ATTRIBUTES, SHIP_3D))
+    }
+
+    fn v5() -> String {
+        format!("(version: 5, {UNIVERSE} cargo: [(7, Ore, 12.5)], sim_time: 86400.0, pilots: [{}])", v1_pilot(ATTRIBUTES, SHIP_3D))
+    }
+
+    /// Universe shared by every version is carried over untouched
+    fn assert_universe_kept(save: &SaveGame) {
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.factions.len(), 2);
+        assert_eq!(save.factions[0].lexicon.first_syllables, vec!["ha".to_string()]);
+        assert_eq!(save.police_faction, Some(0));
+        assert_eq!(save.faction_standings, vec![(1, 0, 0.5)]);
+        assert_eq!((save.anomalies.len(), save.anomalies[0].id), (1, 4));
+        assert_eq!((save.next_pilot_uid, save.name_seed), (8, 42));
+        let pilot = &save.pilots[0];
+        assert_eq!((pilot.u_id, pilot.level, pilot.experience, pilot.respawn_base), (7, 2, 40, Some(2)));
+        assert_eq!(pilot.skills, vec![(0, 1, 250.0)]);
+        let ship = pilot.ship.as_ref().unwrap();
+        assert_eq!(ship.velocity, [3.0, 4.0, 0.0]);
+        assert!(matches!(ship.destination, SavedDestination::Position(at) if at == [5.0, 6.0, 0.0]));
+        assert!(ship.plane_lock.is_none());
+    }
+
+    #[test]
+    fn v1_gets_default_attributes() {
+        let (save, report) = upgrade(&v1()).unwrap();
+        assert_universe_kept(&save);
+        assert_eq!((report.from, report.to, report.changes.len()), (1, SAVE_VERSION, 4));
+        let attributes: HashMap<_, _> = save.pilots[0].attributes.iter().copied().collect();
+        assert_eq!(attributes, PilotAttributes::default().0);
+    }
+
+    #[test]
+    fn v2_keeps_attributes_and_goes_3d() {
+        let (save, report) = upgrade(&v2()).unwrap();
+        assert_universe_kept(&save);
+        assert_eq!((report.from, report.changes.len()), (2, 3));
+        assert_eq!(save.pilots[0].attributes, vec![(Attribute::Perception, 25), (Attribute::Memory, 15)]);
+    }
+
+    #[test]
+    fn v3_gets_an_empty_cargo() {
+        let (save, report) = upgrade(&v3()).unwrap();
+        assert_universe_kept(&save);
+        assert_eq!((report.from, report.changes.len()), (3, 2));
+        assert!(save.cargo.is_empty());
+    }
+
+    #[test]
+    fn v4_keeps_cargo_and_starts_the_clock() {
+        let (save, report) = upgrade(&v4()).unwrap();
+        assert_universe_kept(&save);
+        assert_eq!((report.from, report.changes.len()), (4, 1));
+        assert_eq!(save.cargo, vec![(7, Item::Ore, 12.5)]);
+        assert_eq!(save.sim_time, 0.0);
+    }
+
+    #[test]
+    fn current_saves_load_unchanged() {
+        let (save, report) = upgrade(&v5()).unwrap();
+        assert_universe_kept(&save);
+        assert_eq!(report.from, SAVE_VERSION);
+        assert!(report.changes.is_empty());
+        assert_eq!(save.cargo, vec![(7, Item::Ore, 12.5)]);
+        assert_eq!(save.sim_time, 86400.0);
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        assert!(matches!(upgrade(&save_text(99, v1_pilot("", SHIP_2D))), Err(SaveError::UnsupportedVersion(99))));
+    }
+}
@@ -0,0 +1,255 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::base::timer::{ScheduledEventPlugin, SimClock, SimSchedule};
+use crate::space::combat::Engaging;
+use crate::space::faction::{FactionDef, FactionRegistry, Standings};
+use crate::space::galaxy::{GalaxyCoordinate, SimPosition};
+use crate::space::orders::OrderSystem;
+use crate::space::pilot::{Faction, NameLexicon, Pilot, PilotNameGenerator, spawn_new_pilot};
+use crate::space::save::{PendingLoad, SaveSystem};
+use crate::space::ship::{Destination, DestoType, HullClass, new_ship, TargetLock};
+
+/// Lowest security still considered high-sec
+pub const HIGH_SEC: f32 = 0.45;
+
+/// Seconds a pilot stays flagged after its last offense
+const SUSPECT_DURATION: f32 = 300.0;
+const CRIMINAL_DURATION: f32 = 900.0;
+
+/// Police response delay, in seconds, for a 1.0 system and for the lowest high-sec
+const POLICE_MIN_DELAY: f32 = 6.0;
+const POLICE_MAX_DELAY: f32 = 30.0;
+const POLICE_SQUAD_SIZE: usize = 3;
+
+pub struct SecurityPlugin;
+
+impl Plugin for SecurityPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_plugin(ScheduledEventPlugin::<PoliceResponse>::default())
+            .add_event::<AggressionEvent>()
+            .add_startup_system_to_stage(StartupStage::PostStartup, register_police_faction.after(SaveSystem::Load))
+            .add_system(flag_illegal_aggression.label(SecuritySystem::Flag).after(OrderSystem::Apply))
+            .add_system(tick_criminal_flags.label(SecuritySystem::Tick).after(SecuritySystem::Flag))
+            .add_system(dispatch_police.label(SecuritySystem::Dispatch).after(SecuritySystem::Tick))
+            .add_system(police_pursue.after(SecuritySystem::Dispatch).before(OrderSystem::Follow));
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SecuritySystem {
+    Flag,
+    Tick,
+    Dispatch,
+}
+
+/// Security of a solar system, from -1.0 (lawless) to 1.0 (policed)
+#[derive(Component, Copy, Clone, Deref)]
+pub struct SecurityStatus(pub f32);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SecurityClass {
+    High,
+    Low,
+    Null,
+}
+
+impl SecurityStatus {
+    pub fn class(&self) -> SecurityClass {
+        if self.0 >= HIGH_SEC {
+            SecurityClass::High
+        } else if self.0 > 0.0 {
+            SecurityClass::Low
+        } else {
+            SecurityClass::Null
+        }
+    }
+
+    /// Police show up faster in safer systems
+    pub fn police_delay(&self) -> f32 {
+        let t = ((self.0 - HIGH_SEC) / (1.0 - HIGH_SEC)).clamp(0.0, 1.0);
+        POLICE_MAX_DELAY + (POLICE_MIN_DELAY - POLICE_MAX_DELAY) * t
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FlagKind {
+    /// Free to shoot for everyone, police don't care
+    Suspect,
+    /// Free to shoot for everyone, and police will come
+    Criminal,
+}
+
+/// Outlaw status of a pilot, removed once the timer runs out
+#[derive(Component)]
+pub struct CriminalFlag {
+    pub kind: FlagKind,
+    pub timer: Timer,
+}
+
+/// Marks police ships, they go away once their target is not a criminal anymore
+#[derive(Component)]
+pub struct Police {
+    pub system: Entity,
+}
+
+#[derive(Resource, Deref)]
+pub struct PoliceFaction(pub Faction);
+
+/// `aggressor` engaged `target`, police ships never send those
+pub struct AggressionEvent {
+    pub aggressor: Entity,
+    pub target: Entity,
+}
+
+/// Flag earned by engaging a target in a system of the given class, `None` when legal.
+/// Shooting flagged pilots or pilots the aggressor's faction is at war with is always legal,
+/// so is anything in null-sec.
+pub fn aggression_consequence(class: SecurityClass, target_flagged: bool, target_hostile: bool) -> Option<FlagKind> {
+    if target_flagged || target_hostile {
+        return None;
+    }
+    match class {
+        SecurityClass::High => Some(FlagKind::Criminal),
+        SecurityClass::Low => Some(FlagKind::Suspect),
+        SecurityClass::Null => None,
+    }
+}
+
+/// Police arriving on `criminal`, sent through a [`SimSchedule`] once the system's delay is over
+pub struct PoliceResponse {
+    pub criminal: Entity,
+    pub system: Entity,
+}
+
+pub fn navy_lexicon() -> NameLexicon {
+    NameLexicon::new(
+        &["mar", "jo", "hel", "ed", "wil", "ar"],
+        &["ton", "na", "son", "ric"],
+        &["strand", "cole", "brook", "ward", "hay"],
+    )
+}
+
+/// Add the police faction after the others, loaded universes bring their own
+pub fn register_navy(registry: &mut FactionRegistry, names: &mut PilotNameGenerator) -> Faction {
+    let faction = registry.register(FactionDef {
+        name: "Navy".to_string(),
+        home_systems: Vec::new(),
+        color: Color::WHITE,
+        lexicon: navy_lexicon(),
+    });
+    names.use_lexicons(registry);
+    faction
+}
+
+fn register_police_faction(mut commands: Commands,
+                           pending: Option<Res<PendingLoad>>,
+                           mut registry: ResMut<FactionRegistry>,
+                           mut names: ResMut<PilotNameGenerator>) {
+    if pending.is_some() {
+        return;
+    }
+    let faction = register_navy(&mut registry, &mut names);
+    commands.insert_resource(PoliceFaction(faction));
+}
+
+fn flag_illegal_aggression(mut commands: Commands,
+                           mut events: EventReader<AggressionEvent>,
+                           clock: Res<SimClock>,
+                           mut pending: ResMut<SimSchedule<PoliceResponse>>,
+                           standings: Res<Standings>,
+                           systems: Query<&SecurityStatus>,
+                           mut pilots: Query<(&Pilot, &Faction, &GalaxyCoordinate, Option<&mut CriminalFlag>), Without<Police>>) {
+    for ev in events.iter() {
+        let Ok((_, faction, ..)) = pilots.get(ev.aggressor) else { continue; };
+        let (target_flagged, target_hostile) = match pilots.get(ev.target) {
+            Ok((t_pilot, t_faction, _, flag)) => (flag.is_some(), standings.is_hostile(*faction, t_pilot, *t_faction)),
+            Err(_) => (false, false),
+        };
+        let Ok((_, _, coord, flag)) = pilots.get_mut(ev.aggressor) else { continue; };
+        let Ok(security) = systems.get(coord.0) else { continue; };
+
+        let Some(kind) = aggression_consequence(security.class(), target_flagged, target_hostile) else { continue; };
+        let duration = match kind {
+            FlagKind::Suspect => SUSPECT_DURATION,
+            FlagKind::Criminal => CRIMINAL_DURATION,
+        };
+
+        match flag {
+            Some(mut flag) => {
+                //offenses only ever make things worse
+                if kind == FlagKind::Criminal {
+                    flag.kind = FlagKind::Criminal;
+                }
+                flag.timer = Timer::from_seconds(duration, TimerMode::Once);
+            }
+            None => {
+                commands.entity(ev.aggressor).insert(CriminalFlag {
+                    kind,
+                    timer: Timer::from_seconds(duration, TimerMode::Once),
+                });
+            }
+        }
+
+        if kind == FlagKind::Criminal
+            && !pending.iter().any(|r| r.criminal == ev.aggressor) {
+            pending.after(&clock, Duration::from_secs_f32(security.police_delay()), PoliceResponse {
+                criminal: ev.aggressor,
+                system: coord.0,
+            });
+        }
+    }
+}
+
+fn tick_criminal_flags(mut commands: Commands,
+                       clock: Res<SimClock>,
+                       mut query: Query<(Entity, &mut CriminalFlag)>) {
+    for (entity, mut flag) in &mut query {
+        flag.timer.tick(clock.delta());
+        if flag.timer.finished() {
+            commands.entity(entity).remove::<CriminalFlag>();
+        }
+    }
+}
+
+fn dispatch_police(mut commands: Commands,
+                   police_faction: Res<PoliceFaction>,
+                   mut names: ResMut<PilotNameGenerator>,
+                   mut responses: EventReader<PoliceResponse>,
+                   criminals: Query<(&SimPosition, &GalaxyCoordinate, &CriminalFlag)>) {
+    for response in responses.iter() {
+        let Ok((pos, coord, flag)) = criminals.get(response.criminal) else { continue; };
+        //the criminal got away
+        if coord.0 != response.system || flag.kind != FlagKind::Criminal {
+            continue;
+        }
+        for _ in 0..POLICE_SQUAD_SIZE {
+            commands.spawn((
+                spawn_new_pilot(police_faction.0, &mut names),
+                new_ship(response.system, *pos, DestoType::DPosition(*pos), HullClass::Cruiser),
+                Police { system: response.system },
+                TargetLock(response.criminal),
+                Engaging,
+            ));
+        }
+        info!("police dispatched on {:?}", response.criminal);
+    }
+}
+
+/// Police chase their target and leave once it is not a criminal in their system anymore
+fn police_pursue(mut commands: Commands,
+                 mut police: Query<(Entity, &Police, &TargetLock, &mut Destination)>,
+                 criminals: Query<(&SimPosition, &GalaxyCoordinate, &CriminalFlag)>) {
+    for (entity, cop, lock, mut dest) in &mut police {
+        match criminals.get(lock.0) {
+            Ok((pos, coord, flag)) if coord.0 == cop.system && flag.kind == FlagKind::Criminal => {
+                dest.0 = DestoType::DPosition(*pos);
+            }
+            _ => {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
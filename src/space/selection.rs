@@ -0,0 +1,154 @@
+//! Selecting ships in the system view : click, drag a box, or add to the selection with
+//! [`Action::AddToSelection`] held, shift or control by default. Ships and stations carry a picking [`Selection`] while rendered,
+//! whatever selects them, the overview included, goes through it.
+
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use bevy_mod_picking::Selection;
+
+use crate::base::actions::{Action, ActionState};
+use crate::base::units::{GalaxyScale, Metres};
+use crate::space::galaxy::{Rendered, SimPosition, SolarSystem, ViewState};
+use crate::space::project::ViewCursor;
+use crate::space::ship::HullClass;
+
+/// Clicks this close to a projected entity hit it, in pixels
+pub const PICK_RADIUS: f32 = 12.0;
+/// Shorter drags are clicks, in pixels
+const DRAG_THRESHOLD: f32 = 4.0;
+/// Distance between two ships of a formation
+const FORMATION_SPACING: Metres = Metres(1_500.0);
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        crate::base::ensure_egui(app);
+        app
+            .init_resource::<BoxSelect>()
+            .add_system(box_select)
+            .add_system(selection_count);
+    }
+}
+
+/// Drag in progress, started at a sim position and a screen position in egui points
+#[derive(Resource, Default)]
+pub struct BoxSelect {
+    start: Option<(DVec2, Vec2, egui::Pos2)>,
+}
+
+/// Closest of the projected entities within [`PICK_RADIUS`] of `world`
+pub fn pick<'a>(world: Vec2, shown: impl Iterator<Item=(Entity, &'a Transform)>) -> Option<Entity> {
+    shown
+        .map(|(entity, transform)| (entity, transform.translation.truncate().distance(world)))
+        .filter(|(_, d)| *d <= PICK_RADIUS)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _)| entity)
+}
+
+/// Destinations around `center` for ships at `ships`, the closest ship takes the center and
+/// the others fill hexagonal rings outward, so a fleet doesn't converge on a single point
+pub fn formation(center: DVec2, ships: &[DVec3], scale: &GalaxyScale) -> Vec<DVec2> {
+    let spacing = scale.to_sim(FORMATION_SPACING).0;
+    let mut slots = vec![center];
+    let hex_corner = |side: i32| {
+        let angle = side as f64 * std::f64::consts::FRAC_PI_3;
+        DVec2::new(angle.cos(), angle.sin())
+    };
+    let mut ring = 1;
+    while slots.len() < ships.len() {
+        //6 sides of `ring` slots each
+        for side in 0..6 {
+            let (corner, next) = (hex_corner(side), hex_corner(side + 1));
+            for step in 0..ring {
+                let along = corner.lerp(next, step as f64 / ring as f64);
+                slots.push(center + along * ring as f64 * spacing);
+            }
+        }
+        ring += 1;
+    }
+
+    //nearest ships take the inner slots
+    let mut order: Vec<usize> = (0..ships.len()).collect();
+    order.sort_by(|a, b| ships[*a].truncate().distance(center).total_cmp(&ships[*b].truncate().distance(center)));
+    let mut assigned = vec![center; ships.len()];
+    for (slot, ship) in order.into_iter().enumerate() {
+        assigned[ship] = slots[slot];
+    }
+    assigned
+}
+
+fn box_select(mut egui: ResMut<EguiContext>,
+              mut drag: ResMut<BoxSelect>,
+              actions: Res<ActionState>,
+              view: Res<State<ViewState>>,
+              cursor: ViewCursor,
+              shown: Query<(Entity, &Transform, &SimPosition, Option<&HullClass>), (With<Rendered>, Without<SolarSystem>, Without<Camera>)>,
+              mut selections: Query<&mut Selection, With<Rendered>>) {
+    if *view.current() != ViewState::SYSTEM {
+        drag.start = None;
+        return;
+    }
+    let ctx = egui.ctx_mut();
+    let Some(world) = cursor.world() else { return; };
+    let Some(pointer) = ctx.input().pointer.hover_pos() else { return; };
+
+    if actions.just_pressed(Action::Select) && !ctx.is_pointer_over_area() {
+        drag.start = Some((cursor.to_sim(world), world, pointer));
+    }
+    let Some((start_sim, start_world, start_pointer)) = drag.start else { return; };
+    let dragging = start_world.distance(world) > DRAG_THRESHOLD;
+
+    if actions.pressed(Action::Select) {
+        if dragging {
+            let rect = egui::Rect::from_two_pos(start_pointer, pointer);
+            let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("box_select")));
+            painter.rect_filled(rect, 0.0, egui::Color32::from_rgba_unmultiplied(120, 160, 255, 24));
+            painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, egui::Color32::from_rgb(120, 160, 255)));
+        }
+        return;
+    }
+    drag.start = None;
+
+    let picked: Vec<Entity> = if dragging {
+        let end_sim = cursor.to_sim(world);
+        let (min, max) = (start_sim.min(end_sim), start_sim.max(end_sim));
+        shown.iter()
+            .filter(|(_, _, pos, hull)| {
+                let p = pos.0.truncate();
+                hull.is_some() && p.cmpge(min).all() && p.cmple(max).all()
+            })
+            .map(|(entity, ..)| entity)
+            .collect()
+    } else {
+        pick(world, shown.iter().map(|(entity, transform, ..)| (entity, transform))).into_iter().collect()
+    };
+
+    let add = actions.pressed(Action::AddToSelection);
+    for (entity, ..) in shown.iter() {
+        let Ok(mut selection) = selections.get_mut(entity) else { continue; };
+        let selected = picked.contains(&entity) || (add && selection.selected());
+        if selection.selected() != selected {
+            selection.set_selected(selected);
+        }
+    }
+}
+
+/// How many ships are selected, bottom left
+fn selection_count(mut egui: ResMut<EguiContext>,
+                   view: Res<State<ViewState>>,
+                   selected: Query<&Selection, (With<Rendered>, With<HullClass>)>) {
+    if *view.current() != ViewState::SYSTEM {
+        return;
+    }
+    let count = selected.iter().filter(|s| s.selected()).count();
+    if count == 0 {
+        return;
+    }
+    egui::Area::new("selection_count")
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(8.0, -8.0))
+        .show(egui.ctx_mut(), |ui| {
+            ui.monospace(format!("{} ship{} selected", count, if count > 1 { "s" } else { "" }));
+        });
+}
@@ -1,55 +1,63 @@
-use std::cmp::max;
-
-use bevy::{ecs::component, prelude::*, transform::components};
-use bevy::math::{DVec2, DVec3, Vec3Swizzles};
+use bevy::prelude::*;
+use bevy::math::DVec3;
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 
+use crate::base::rng::SimRng;
+use crate::base::timer::SimClock;
+use crate::base::units::{GalaxyScale, Metres, SimUnits};
 use crate::base::velocity::*;
 use crate::space::galaxy::SimPosition;
+use crate::space::orders::Docked;
 use crate::space::pilot::*;
+use crate::space::progression::PilotLevelUp;
+use crate::space::skills::{SkillBonuses, SkillRequirements, Skills, SkillTrainedEvent, SkillTree};
 
 use super::galaxy::GalaxyCoordinate;
 
-#[path = "../base/velocity.rs"]
-pub mod velocity;
-
-#[path = "../space/pilot.rs"]
-pub mod pilot;
-
 
 ///TODO should schedule only a few times per frame
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ShipSystem {
+    Forces,
+}
+
 pub fn compute_ship_forces(
-    time: Res<Time>,
-    mut query: Query<(&mut Velocity, &SimPosition, &Destination, &Mass, &ThrusterEngine, )>) {
-    query.par_for_each_mut(8, |(mut vel, sPos, dest, mass, thruster)|
+    clock: Res<SimClock>,
+    scale: Res<GalaxyScale>,
+    mut query: Query<(&mut Velocity, &SimPosition, &Destination, &Mass, &ThrusterEngine, Option<&SkillBonuses>, Option<&PlaneLock>)>) {
+    let scale = *scale;
+    let dt = clock.delta_seconds_f64();
+    query.par_for_each_mut(8, |(mut vel, s_pos, dest, mass, thruster, bonuses, lock)|
         {
             let desto_type: &DestoType = &dest.0;
-            let direction: Option<DVec2> = DVec2 { x: vel.x, y: vel.y }.try_normalize();
+            let direction: Option<DVec3> = vel.0.try_normalize();
             let amplitude: f64 = vel.length();
-            let accel: f64 = get_accel(mass, thruster);
-            let drag: DVec2;
-
-            match direction {
-                None => { drag = DVec2::ZERO }
-                Some(dir) => {
-                    drag = -dir * (0.02 * 0.35 * ((amplitude * amplitude)));
-                }
-            }
-
-            let mut thrust_dir: Option<DVec2> = None;
-            let dist: f64;
-
-            match desto_type {
-                DestoType::DPosition(dPos) => {
-                    dist = (*dPos - sPos.0.truncate()).length() / 0.000001;
-                    thrust_dir = Some((*dPos - sPos.0.truncate()).normalize());
-                }
-                DestoType::TEntity(dPos) => {
-                    dist = (dPos.0.truncate() - sPos.0.truncate()).length() / 0.000001;
-                    thrust_dir = Some((dPos.0.truncate() - sPos.0.truncate()).normalize());
+            let accel: f64 = get_accel(mass, thruster) * bonuses.map_or(1.0, |b| b.thrust as f64);
+            let drag: DVec3 = match direction {
+                None => DVec3::ZERO,
+                Some(dir) => -dir * (0.02 * 0.35 * (amplitude * amplitude)),
+            };
+
+            let mut thrust_dir: Option<DVec3> = None;
+            let dist: Metres;
+
+            let target = match desto_type {
+                DestoType::DPosition(d_pos) => Some(d_pos.0),
+                DestoType::TEntity(d_pos) => Some(d_pos.0),
+                DestoType::None => None,
+            };
+            match target {
+                Some(mut target) => {
+                    //locked ships aim at the target's projection on their plane
+                    if let Some(lock) = lock {
+                        target.z = lock.0;
+                    }
+                    dist = scale.to_metres(SimUnits((target - s_pos.0).length()));
+                    thrust_dir = (target - s_pos.0).try_normalize();
                 }
-                DestoType::None => {
-                    dist = 0.0;
+                None => {
+                    dist = Metres(0.0);
                 }
             }
 
@@ -57,41 +65,32 @@ pub fn compute_ship_forces(
             match thrust_dir {
                 None => {
                     let local_vel = vel.0;
-                    vel.0 += (drag - local_vel.normalize() * accel) * time.delta_seconds_f64()
+                    vel.0 += (drag - local_vel.normalize_or_zero() * accel) * dt
                 }
                 Some(dir) => {
-                    let local_vel: DVec2 = vel.0;
-                    let brake = dist / accel < local_vel.length() / accel;
+                    let local_vel: DVec3 = vel.0;
+                    let brake = dist.0 / accel < local_vel.length() / accel;
 
                     if brake {
-                        vel.0 += (drag - local_vel.normalize() * accel) * time.delta_seconds_f64()
+                        vel.0 += (drag - local_vel.normalize_or_zero() * accel) * dt
                     } else {
-                        vel.0 += ((drag +
+                        vel.0 += (drag +
                             dir * accel
-                        )) * time.delta_seconds_f64();
+                        ) * dt;
                     }
 
                     //println!("vel  = {:?}, accel = {:?}, drag = {:?}, dist = {:?}, value = {:?}", amplitude, accel, drag.length(),dist, 0.0);
                 }
             }
-        });
-}
 
-fn get_delta_velocity(from: &DVec2, to: &DVec2, m: &Mass, th: &ThrusterEngine, dt: f64) -> Option<DVec2> {
-    let dir = (*from - *to).try_normalize();
-    match dir {
-        Some(d) => {
-            return Some(d * (get_accel(m, th) * dt));
-        }
-        None => {
-            return None;
-        }
-    }
+            let max_speed = thruster.max_speed * bonuses.map_or(1.0, |b| b.max_speed as f64);
+            vel.0 = vel.0.clamp_length_max(max_speed);
+        });
 }
 
 #[inline]
 fn get_accel(m: &Mass, th: &ThrusterEngine) -> f64 {
-    return (th.thrust / m.0) as f64;
+    (th.thrust / m.0) as f64
 }
 
 #[derive(Component)]
@@ -99,49 +98,83 @@ fn get_accel(m: &Mass, th: &ThrusterEngine) -> f64 {
 pub struct UndockLoc;
 
 
+/// Ships leave at most this far from the station
+const UNDOCK_SCATTER: Metres = Metres(200.0);
+
 pub fn undock_pilot_system(
     mut commands: Commands,
-    query: Query<(Entity, &UndockingFrom)>,
+    scale: Res<GalaxyScale>,
+    mut rng: ResMut<SimRng>,
+    tree: Res<SkillTree>,
+    query: Query<(Entity, &UndockingFrom, &Pilot, &Skills, &RespawnBase)>,
     undocks: Query<(&SimPosition,&GalaxyCoordinate) , With<UndockLoc>>) {
-    let mut rng = thread_rng();
-    for (entity, from) in query.iter() {
+    let scatter = scale.to_sim(UNDOCK_SCATTER).0;
+    //draw in pilot order so the same seed scatters the same way whatever the entity layout
+    let mut undocking: Vec<_> = query.iter().collect();
+    undocking.sort_by_key(|(_, _, pilot, ..)| pilot.u_id);
+    for (entity, from, pilot, skills, respawn) in undocking {
         if let Ok(trans) = undocks.get(commands.entity(from.0).id()) {
             commands.entity(entity).insert(
-                ShipBundle {
-                    display: SpriteBundle {
-                        sprite: Sprite {
-                            color: Color::rgb(0.25, 0.25, 0.75),
-                            custom_size: Some(Vec2::new(16.0, 16.0)),
-                            ..default()
-                        },
-                        transform: Transform {
-                            translation: Vec3::ZERO,
-                            ..default()
-                        },
-                        visibility: Visibility { is_visible: false },
-                        ..default()
-                    },
-                    movable: MovableBundle {
-                        coordinate: GalaxyCoordinate(trans.1.0),
-                        simulation_position: SimPosition((trans.0.0 * 3.0) * 0.000001),
-                        mass: Mass(1500000),
-                        velocity: Velocity::default(),
-                        thruster: ThrusterEngine {
-                            max_speed: 100.0,
-                            thrust: 100000000,
-                            angular: 25.15,
-                        },
-                        move_towards: Destination(DestoType::DPosition(DVec2 {
-                            x: rng.gen_range(-0.0002..0.0002),
-                            y: rng.gen_range(-0.00015..0.00015),
-                        })),
-                    },
-                }
-            ).remove::<UndockingFrom>();
+                new_ship(
+                    trans.1.0,
+                    SimPosition(trans.0.0),
+                    DestoType::DPosition(SimPosition(trans.0.0 + DVec3 {
+                        x: rng.gen_range(-scatter..scatter),
+                        y: rng.gen_range(-scatter * 0.75..scatter * 0.75),
+                        z: rng.gen_range(-scatter * 0.25..scatter * 0.25),
+                    })),
+                    HullClass::best_for(pilot.level, skills, &tree),
+                )
+            ).remove::<UndockingFrom>().remove::<Docked>();
+            //the first station a pilot leaves becomes its home
+            if respawn.0.is_none() {
+                commands.entity(entity).insert(RespawnBase(Some(from.0)));
+            }
         } else {println!("invalid pos")}
     }
 }
 
+///Stock ship of the given class, simulation only, its sprite comes when its system gets rendered
+pub fn new_ship(system: Entity, at: SimPosition, destination: DestoType, hull: HullClass) -> ShipBundle {
+    ShipBundle {
+        movable: MovableBundle {
+            coordinate: GalaxyCoordinate(system),
+            simulation_position: at,
+            mass: hull.mass(),
+            velocity: Velocity::default(),
+            thruster: hull.thruster(),
+            move_towards: Destination(destination),
+        },
+        health: hull.health(),
+        hull,
+    }
+}
+
+///Pilots move up to the biggest hull their level and skills allow
+pub fn upgrade_hull_on_level_up(
+    tree: Res<SkillTree>,
+    mut level_ups: EventReader<PilotLevelUp>,
+    mut trained: EventReader<SkillTrainedEvent>,
+    mut query: Query<(&Pilot, &Skills, &mut HullClass, &mut Mass, &mut ThrusterEngine, &mut Health)>) {
+    let pilots = level_ups.iter().map(|ev| ev.pilot).chain(trained.iter().map(|ev| ev.pilot));
+    for pilot in pilots {
+        if let Ok((pilot, skills, mut hull, mut mass, mut thruster, mut health)) = query.get_mut(pilot) {
+            let best = HullClass::best_for(pilot.level, skills, &tree);
+            if best > *hull {
+                *hull = best;
+                *mass = best.mass();
+                *thruster = best.thruster();
+                *health = best.health();
+            }
+        }
+    }
+}
+
+
+///Entity currently locked by the ship's sensors
+#[derive(Component, Deref)]
+#[component(storage = "SparseSet")]
+pub struct TargetLock(pub Entity);
 
 ///Flag to schedule a ship undock during the next frame
 #[derive(Component)]
@@ -150,8 +183,101 @@ pub struct UndockingFrom(pub Entity);
 
 #[derive(Bundle)]
 pub struct ShipBundle {
-    display: SpriteBundle,
     movable: MovableBundle,
+    health: Health,
+    hull: HullClass,
+}
+
+///Size category of a ship, bigger hulls need a higher pilot level
+#[derive(Component, Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum HullClass {
+    Frigate,
+    Destroyer,
+    Cruiser,
+    Battleship,
+}
+
+impl HullClass {
+    pub const ALL: [HullClass; 4] = [HullClass::Frigate, HullClass::Destroyer, HullClass::Cruiser, HullClass::Battleship];
+
+    pub fn required_level(&self) -> u8 {
+        match self {
+            HullClass::Frigate => 1,
+            HullClass::Destroyer => 5,
+            HullClass::Cruiser => 10,
+            HullClass::Battleship => 20,
+        }
+    }
+
+    /// Skills to train before flying this hull, on top of the level
+    pub fn skill_requirements(&self, tree: &SkillTree) -> SkillRequirements {
+        let needs = match self {
+            HullClass::Frigate => vec![],
+            HullClass::Destroyer => vec![("Frigates", 3)],
+            HullClass::Cruiser => vec![("Cruisers", 1)],
+            HullClass::Battleship => vec![("Cruisers", 4)],
+        };
+        SkillRequirements(needs.into_iter()
+            .filter_map(|(name, level)| tree.find(name).map(|id| (id, level)))
+            .collect())
+    }
+
+    pub fn can_fly(&self, pilot_level: u8, skills: &Skills, tree: &SkillTree) -> bool {
+        pilot_level >= self.required_level() && skills.meets(&self.skill_requirements(tree))
+    }
+
+    pub fn best_for(pilot_level: u8, skills: &Skills, tree: &SkillTree) -> HullClass {
+        HullClass::ALL.into_iter()
+            .filter(|h| h.can_fly(pilot_level, skills, tree))
+            .max()
+            .unwrap_or(HullClass::Frigate)
+    }
+
+    fn mass(&self) -> Mass {
+        Mass(match self {
+            HullClass::Frigate => 1_500_000,
+            HullClass::Destroyer => 2_000_000,
+            HullClass::Cruiser => 10_000_000,
+            HullClass::Battleship => 100_000_000,
+        })
+    }
+
+    fn thruster(&self) -> ThrusterEngine {
+        match self {
+            HullClass::Frigate => ThrusterEngine { max_speed: 100.0, thrust: 100_000_000, angular: 25.15 },
+            HullClass::Destroyer => ThrusterEngine { max_speed: 80.0, thrust: 120_000_000, angular: 18.0 },
+            HullClass::Cruiser => ThrusterEngine { max_speed: 60.0, thrust: 500_000_000, angular: 9.0 },
+            HullClass::Battleship => ThrusterEngine { max_speed: 40.0, thrust: 3_000_000_000, angular: 3.0 },
+        }
+    }
+
+    fn health(&self) -> Health {
+        match self {
+            HullClass::Frigate => Health::new(400.0, 300.0, 300.0),
+            HullClass::Destroyer => Health::new(600.0, 500.0, 400.0),
+            HullClass::Cruiser => Health::new(2_000.0, 1_800.0, 1_200.0),
+            HullClass::Battleship => Health::new(8_000.0, 7_000.0, 5_000.0),
+        }
+    }
+
+    /// Damage dealt each second to an engaged target, before skill bonuses
+    pub fn weapon_dps(&self) -> f32 {
+        match self {
+            HullClass::Frigate => 40.0,
+            HullClass::Destroyer => 60.0,
+            HullClass::Cruiser => 120.0,
+            HullClass::Battleship => 300.0,
+        }
+    }
+
+    pub(crate) fn sprite_size(&self) -> f32 {
+        match self {
+            HullClass::Frigate => 16.0,
+            HullClass::Destroyer => 20.0,
+            HullClass::Cruiser => 28.0,
+            HullClass::Battleship => 40.0,
+        }
+    }
 }
 
 ///Anything movable should be made with this bundle
@@ -182,6 +308,8 @@ pub struct DamageableBundle {
 pub struct Mass(u64);
 
 
+//`angular` has no reader until ships turn
+#[allow(dead_code)]
 #[derive(Component)]
 pub struct ThrusterEngine {
     // m/s
@@ -192,6 +320,8 @@ pub struct ThrusterEngine {
     angular: f32,
 }
 
+//Warp drives jump at a fixed pace for now, nothing reads these
+#[allow(dead_code)]
 #[derive(Component)]
 pub struct WarpEngine {
     range: f64,
@@ -200,6 +330,8 @@ pub struct WarpEngine {
 }
 
 
+//The max values are only kept for repairs, which do not exist yet
+#[allow(dead_code)]
 #[derive(Component)]
 pub struct Health {
     current_structure: f32,
@@ -210,9 +342,37 @@ pub struct Health {
     max_shield: f32,
 }
 
+impl Health {
+    pub fn new(shield: f32, armor: f32, structure: f32) -> Self {
+        Self {
+            current_structure: structure,
+            max_structure: structure,
+            current_armor: armor,
+            max_armor: armor,
+            current_shield: shield,
+            max_shield: shield,
+        }
+    }
+
+    /// Shield goes first, then armor, then structure
+    pub fn damage(&mut self, amount: f32) {
+        let mut left = amount;
+        for layer in [&mut self.current_shield, &mut self.current_armor, &mut self.current_structure] {
+            let taken = left.min(*layer);
+            *layer -= taken;
+            left -= taken;
+        }
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.current_structure <= 0.0
+    }
+}
+
 #[derive(Default)]
 pub enum DestoType {
-    DPosition(DVec2),
+    /// Point of the ship's system
+    DPosition(SimPosition),
     TEntity(SimPosition),
     #[default]
     None,
@@ -0,0 +1,298 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::base::timer::SimClock;
+
+pub const MAX_SKILL_LEVEL: u8 = 5;
+
+pub struct SkillPlugin;
+
+impl Plugin for SkillPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(SkillTree::default())
+            .add_event::<SkillTrainedEvent>()
+            .add_system(npc_plan_training.label(SkillSystem::Plan))
+            .add_system(train_skills.label(SkillSystem::Train).after(SkillSystem::Plan))
+            .add_system(update_skill_bonuses.label(SkillSystem::Bonuses).after(SkillSystem::Train));
+    }
+}
+
+#[derive(SystemLabel, Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SkillSystem {
+    Plan,
+    Train,
+    Bonuses,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Attribute {
+    Perception,
+    Memory,
+    Willpower,
+    Intelligence,
+    Charisma,
+}
+
+/// Index into the [`SkillTree`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SkillId(pub u16);
+
+/// What a skill does for every level trained
+#[derive(Debug, Copy, Clone)]
+pub enum SkillEffect {
+    None,
+    MiningYield(f32),
+    WeaponDamage(f32),
+    Thrust(f32),
+    MaxSpeed(f32),
+}
+
+pub struct SkillDef {
+    pub name: String,
+    /// Training time multiplier
+    pub rank: u8,
+    pub primary: Attribute,
+    pub secondary: Attribute,
+    pub prerequisites: Vec<(SkillId, u8)>,
+    pub effect: SkillEffect,
+}
+
+impl SkillDef {
+    /// Skill points needed to reach `level`, grows ~x5.66 every level
+    pub fn points_for_level(&self, level: u8) -> f64 {
+        if level == 0 {
+            return 0.0;
+        }
+        250.0 * self.rank as f64 * 32f64.sqrt().powi(level as i32 - 1)
+    }
+}
+
+#[derive(Resource)]
+pub struct SkillTree(pub Vec<SkillDef>);
+
+impl SkillTree {
+    pub fn get(&self, id: SkillId) -> Option<&SkillDef> {
+        self.0.get(id.0 as usize)
+    }
+
+    pub fn find(&self, name: &str) -> Option<SkillId> {
+        self.0.iter().position(|s| s.name == name).map(|i| SkillId(i as u16))
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item=SkillId> {
+        (0..self.0.len() as u16).map(SkillId)
+    }
+}
+
+impl Default for SkillTree {
+    fn default() -> Self {
+        use Attribute::*;
+        let skill = |name: &str, rank, primary, secondary, prerequisites: Vec<(u16, u8)>, effect| SkillDef {
+            name: name.to_string(),
+            rank,
+            primary,
+            secondary,
+            prerequisites: prerequisites.into_iter().map(|(id, lvl)| (SkillId(id), lvl)).collect(),
+            effect,
+        };
+        Self(vec![
+            /* 0 */ skill("Spaceship Command", 1, Perception, Willpower, vec![], SkillEffect::None),
+            /* 1 */ skill("Navigation", 1, Intelligence, Perception, vec![], SkillEffect::MaxSpeed(0.05)),
+            /* 2 */ skill("Acceleration Control", 4, Intelligence, Perception, vec![(1, 3)], SkillEffect::Thrust(0.05)),
+            /* 3 */ skill("Mining", 1, Memory, Intelligence, vec![], SkillEffect::MiningYield(0.05)),
+            /* 4 */ skill("Mining Upgrades", 4, Memory, Intelligence, vec![(3, 4)], SkillEffect::MiningYield(0.05)),
+            /* 5 */ skill("Gunnery", 1, Perception, Willpower, vec![], SkillEffect::WeaponDamage(0.02)),
+            /* 6 */ skill("Small Turrets", 2, Perception, Willpower, vec![(5, 1)], SkillEffect::WeaponDamage(0.05)),
+            /* 7 */ skill("Frigates", 2, Perception, Willpower, vec![(0, 1)], SkillEffect::None),
+            /* 8 */ skill("Cruisers", 5, Perception, Willpower, vec![(7, 3)], SkillEffect::None),
+        ])
+    }
+}
+
+#[derive(Component)]
+pub struct PilotAttributes(pub HashMap<Attribute, u8>);
+
+impl PilotAttributes {
+    pub fn get(&self, attribute: Attribute) -> u8 {
+        *self.0.get(&attribute).unwrap_or(&17)
+    }
+
+    /// Skill points gained per second of training
+    pub fn training_rate(&self, skill: &SkillDef) -> f64 {
+        (self.get(skill.primary) as f64 + self.get(skill.secondary) as f64 / 2.0) / 60.0
+    }
+}
+
+impl Default for PilotAttributes {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (Attribute::Perception, 20),
+            (Attribute::Memory, 20),
+            (Attribute::Willpower, 20),
+            (Attribute::Intelligence, 20),
+            (Attribute::Charisma, 19),
+        ]))
+    }
+}
+
+pub struct TrainedSkill {
+    pub level: u8,
+    pub points: f64,
+}
+
+/// Skills known by a pilot
+#[derive(Component, Default)]
+pub struct Skills(pub HashMap<SkillId, TrainedSkill>);
+
+impl Skills {
+    pub fn level(&self, id: SkillId) -> u8 {
+        self.0.get(&id).map_or(0, |s| s.level)
+    }
+
+    pub fn meets(&self, requirements: &[(SkillId, u8)]) -> bool {
+        requirements.iter().all(|(id, level)| self.level(*id) >= *level)
+    }
+}
+
+/// Skills needed to fly or fit something, see [`HullClass::skill_requirements`](crate::space::ship::HullClass::skill_requirements)
+#[derive(Component, Default, Deref)]
+pub struct SkillRequirements(pub Vec<(SkillId, u8)>);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TrainingError {
+    UnknownSkill,
+    AlreadyTrained,
+    MissingPrerequisite(SkillId, u8),
+}
+
+/// Skills waiting to be trained, the front one is in training
+#[derive(Component, Default)]
+pub struct TrainingQueue(pub VecDeque<(SkillId, u8)>);
+
+impl TrainingQueue {
+    /// Highest level of a skill once everything queued is trained
+    fn planned_level(&self, skills: &Skills, id: SkillId) -> u8 {
+        self.0.iter()
+            .filter(|(s, _)| *s == id)
+            .map(|(_, l)| *l)
+            .max()
+            .unwrap_or(0)
+            .max(skills.level(id))
+    }
+
+    /// Queue every missing level of `id` up to `level`, prerequisites have to be trained or queued before
+    pub fn enqueue(&mut self, tree: &SkillTree, skills: &Skills, id: SkillId, level: u8) -> Result<(), TrainingError> {
+        let def = tree.get(id).ok_or(TrainingError::UnknownSkill)?;
+        let level = level.min(MAX_SKILL_LEVEL);
+        let from = self.planned_level(skills, id);
+        if from >= level {
+            return Err(TrainingError::AlreadyTrained);
+        }
+        for (pre, pre_level) in def.prerequisites.iter() {
+            if self.planned_level(skills, *pre) < *pre_level {
+                return Err(TrainingError::MissingPrerequisite(*pre, *pre_level));
+            }
+        }
+        for l in from + 1..=level {
+            self.0.push_back((id, l));
+        }
+        Ok(())
+    }
+}
+
+/// Multipliers derived from the trained skills, 1.0 is no bonus
+#[derive(Component, Debug, Copy, Clone)]
+pub struct SkillBonuses {
+    pub mining_yield: f32,
+    pub weapon_damage: f32,
+    pub thrust: f32,
+    pub max_speed: f32,
+}
+
+impl Default for SkillBonuses {
+    fn default() -> Self {
+        Self { mining_yield: 1.0, weapon_damage: 1.0, thrust: 1.0, max_speed: 1.0 }
+    }
+}
+
+impl SkillBonuses {
+    pub fn from_skills(tree: &SkillTree, skills: &Skills) -> Self {
+        let mut bonuses = Self::default();
+        //in tree order, float products must not depend on the map order
+        for (id, def) in tree.ids().zip(tree.0.iter()) {
+            let Some(trained) = skills.0.get(&id) else { continue; };
+            let lvl = trained.level as f32;
+            match def.effect {
+                SkillEffect::None => {}
+                SkillEffect::MiningYield(v) => bonuses.mining_yield *= 1.0 + v * lvl,
+                SkillEffect::WeaponDamage(v) => bonuses.weapon_damage *= 1.0 + v * lvl,
+                SkillEffect::Thrust(v) => bonuses.thrust *= 1.0 + v * lvl,
+                SkillEffect::MaxSpeed(v) => bonuses.max_speed *= 1.0 + v * lvl,
+            }
+        }
+        bonuses
+    }
+}
+
+pub struct SkillTrainedEvent {
+    pub pilot: Entity,
+    pub skill: SkillId,
+    pub level: u8,
+}
+
+fn train_skills(clock: Res<SimClock>,
+                tree: Res<SkillTree>,
+                mut query: Query<(Entity, &PilotAttributes, &mut Skills, &mut TrainingQueue)>,
+                mut trained: EventWriter<SkillTrainedEvent>) {
+    for (entity, attributes, mut skills, mut queue) in &mut query {
+        let Some((id, level)) = queue.0.front().copied() else { continue; };
+        let Some(def) = tree.get(id) else {
+            queue.0.pop_front();
+            continue;
+        };
+
+        let entry = skills.0.entry(id).or_insert(TrainedSkill { level: 0, points: 0.0 });
+        entry.points += attributes.training_rate(def) * clock.delta_seconds_f64();
+        if entry.points >= def.points_for_level(level) {
+            entry.level = entry.level.max(level);
+            queue.0.pop_front();
+            trained.send(SkillTrainedEvent { pilot: entity, skill: id, level });
+        }
+    }
+}
+
+/// NPCs with nothing to train pick the cheapest next level they can queue
+fn npc_plan_training(tree: Res<SkillTree>,
+                     mut query: Query<(&Skills, &mut TrainingQueue)>) {
+    for (skills, mut queue) in &mut query {
+        if !queue.0.is_empty() {
+            continue;
+        }
+        let next = tree.ids()
+            .filter_map(|id| {
+                let level = skills.level(id) + 1;
+                let def = tree.get(id)?;
+                if level > MAX_SKILL_LEVEL || !skills.meets(&def.prerequisites) {
+                    return None;
+                }
+                Some((id, level, def.points_for_level(level)))
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        if let Some((id, level, _)) = next {
+            queue.enqueue(&tree, skills, id, level).ok();
+        }
+    }
+}
+
+fn update_skill_bonuses(tree: Res<SkillTree>,
+                        mut events: EventReader<SkillTrainedEvent>,
+                        mut query: Query<(&Skills, &mut SkillBonuses)>) {
+    for ev in events.iter() {
+        if let Ok((skills, mut bonuses)) = query.get_mut(ev.pilot) {
+            *bonuses = SkillBonuses::from_skills(&tree, skills);
+        }
+    }
+}
@@ -1,29 +1,24 @@
 use bevy::prelude::*;
 use crate::{GalaxyCoordinate, SimPosition};
+use crate::space::pilot::Faction;
 
+#[derive(Component)]
+pub struct Station;
+
+/// Simulation side of a station, its sprite comes when its system gets rendered
 #[derive(Bundle)]
 pub struct AnchorableBundle {
-    display: SpriteBundle,
+    tag : Station,
     sim_pos : SimPosition,
-    galaxy_pos :GalaxyCoordinate
+    galaxy_pos :GalaxyCoordinate,
+    owner : Faction,
 }
 
-pub fn spawn_station_at(at : SimPosition, galaxy : Entity ) -> AnchorableBundle{
-    return AnchorableBundle{
-        display: SpriteBundle {
-            sprite: Sprite {
-                color: Color::rgb(0.25, 0.85, 0.15),
-                custom_size: Some(Vec2::new(24.0, 26.0)),
-                ..default()
-            },
-            transform: Transform {
-                translation: Vec3::ZERO,
-                ..default()
-            },
-            visibility: Visibility { is_visible: false },
-            ..default()
-        },
-        sim_pos: at.clone(),
-        galaxy_pos: GalaxyCoordinate(galaxy)
+pub fn spawn_station_at(at : SimPosition, galaxy : Entity, owner : Faction) -> AnchorableBundle{
+    AnchorableBundle{
+        tag: Station,
+        sim_pos: at,
+        galaxy_pos: GalaxyCoordinate(galaxy),
+        owner,
     }
 }
\ No newline at end of file